@@ -0,0 +1,86 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use lib::{load_from_cache, write_cache_as, CacheFormat, Config, FinalConfig, BONNIE_VERSION};
+use std::env;
+use std::fs;
+
+// The number of scripts in the generated configuration, which is far more than any real configuration should have
+const NUM_SCRIPTS: usize = 500;
+
+// Generates a large configuration that uses most of Bonnie's features, so every part of the schema is exercised
+fn get_large_cfg_str() -> String {
+    let mut cfg_str = format!(
+        "version = \"{}\"\ndefault_shell.generic = [\"sh\", \"-c\", \"{{COMMAND}}\"]\n[scripts]\n",
+        BONNIE_VERSION
+    );
+    for i in 0..NUM_SCRIPTS {
+        cfg_str += &format!(
+            r#"script{i}.desc = "Script number {i}"
+script{i}.args = ["name"]
+script{i}.env_vars = ["HOME"]
+script{i}.subcommands.build.cmd.generic = "echo Building %name %%"
+script{i}.subcommands.build.cmd.targets.linux = ["echo Building on Linux", "echo %HOME"]
+script{i}.subcommands.test = "echo Testing %name"
+script{i}.subcommands.clean.cmd = "echo Cleaning"
+script{i}.aliases = ["s{i}"]
+script{i}.order = """
+build {{
+    Success => test,
+    Failure => clean
+}}
+"""
+"#,
+            i = i
+        );
+    }
+
+    cfg_str
+}
+
+// Parses the configuration at the given path from scratch, as Bonnie does when there's no cache
+fn parse_cfg(cfg_path: &str) -> FinalConfig {
+    let cfg_str = fs::read_to_string(cfg_path).unwrap();
+    Config::new(&cfg_str)
+        .unwrap()
+        .load_includes(cfg_path)
+        .unwrap()
+        .to_final(BONNIE_VERSION, &mut Vec::new())
+        .unwrap()
+}
+
+fn startup(c: &mut Criterion) {
+    let dir = env::temp_dir().join("bonnie_bench_startup");
+    fs::create_dir_all(&dir).unwrap();
+    let path = |file: &str| -> String { dir.join(file).to_string_lossy().to_string() };
+    let cfg_path = path("bonnie.toml");
+    fs::write(&cfg_path, get_large_cfg_str()).unwrap();
+    let cfg = parse_cfg(&cfg_path);
+
+    #[allow(unused_mut)] // This is only added to when binary caching is enabled
+    let mut formats = vec![("json_cache", CacheFormat::Json)];
+    #[cfg(feature = "binary-cache")]
+    formats.push(("binary_cache", CacheFormat::Binary));
+    let cache_paths: Vec<(&str, String)> = formats
+        .into_iter()
+        .map(|(name, format)| {
+            let cache_path = path(&format!("{}.cache", name));
+            write_cache_as(&cfg, Some(&cache_path), format).unwrap();
+            (name, cache_path)
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("startup");
+    group.bench_function("toml_parse", |b| b.iter(|| parse_cfg(&cfg_path)));
+    for (name, cache_path) in &cache_paths {
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                load_from_cache(&mut Vec::new(), Some(cache_path))
+                    .unwrap()
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, startup);
+criterion_main!(benches);
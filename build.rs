@@ -0,0 +1,8 @@
+// This makes the target triple Bonnie is being built for available to the code (Cargo only gives it to build scripts), so scripts can be selected by it
+fn main() {
+    println!(
+        "cargo:rustc-env=BONNIE_BUILD_TARGET={}",
+        std::env::var("TARGET").unwrap()
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -1,4 +1,7 @@
-use lib::{cache, cache_exists, get_cfg, help, init, load_from_cache, Config, BONNIE_VERSION};
+use lib::{
+    cache, cache_exists, get_cfg, get_cfg_path, handle_interrupts, help, init, load_from_cache,
+    run_workspace, write_cache, Config, ConfigFormat, GraphFormat, BONNIE_VERSION,
+};
 use std::env;
 use std::io::Write;
 
@@ -35,12 +38,15 @@ fn core() -> Result<i32, String> {
     // TODO add a checker for the executable that offers to install Bonnie if it isn't already?
     let _executable_name = prog_args.remove(0);
     // Get the file we'll be using
-    let cfg_path = env::var("BONNIE_CONF").unwrap_or_else(|_| "./bonnie.toml".to_string());
+    let cfg_path = get_cfg_path()?;
     // Check for special arguments
     let mut should_cache = false;
     let mut verbose = false;
+    let mut dry_run = false;
+    let mut all = false;
     let mut document = false;
-    if matches!(prog_args.get(0), Some(_)) {
+    let mut graph = false;
+    if !prog_args.is_empty() {
         if prog_args[0] == "-v" || prog_args[0] == "--version" {
             writeln!(stdout, "You are currently running Bonnie v{}! You can see the latest release at https://github.com/arctic-hen7/bonnie/releases.", BONNIE_VERSION).expect("Failed to write version.");
             return Ok(0);
@@ -71,25 +77,52 @@ fn core() -> Result<i32, String> {
             // This can be specified with a command following
             verbose = true;
             prog_args.remove(0);
+        } else if prog_args[0] == "--dry-run" {
+            // This is also specified with a command following
+            dry_run = true;
+            prog_args.remove(0);
+        } else if prog_args[0] == "--all" {
+            // This runs the following command in every workspace member instead of here
+            all = true;
+            prog_args.remove(0);
         }
         // Check if the user wants the configuration's help page (self-documenting)
         // TODO 'doc' instead/as well?
         else if prog_args[0] == "help" {
             document = true;
         }
+        // Check if the user wants a diagram of a command's ordered subcommands
+        else if prog_args[0] == "graph" {
+            graph = true;
+        }
     }
     // Check if there's a cache we should read from
     // If there is but we're explicitly recaching, we should of course read directly from the source file
-    let cfg;
-    if cache_exists()? && !should_cache {
-        cfg = load_from_cache(stdout, None)?;
+    // If the cache is stale (something it was built from has changed), we rebuild it transparently
+    let cached_cfg = if cache_exists()? && !should_cache {
+        load_from_cache(stdout, None)?
     } else {
-        // Get the config as a string
-        let cfg_str = get_cfg()?;
-        // Create a raw config object and parse it fully
-        // We use `stdout` for printing warnings
-        cfg = Config::new(&cfg_str)?.to_final(BONNIE_VERSION, stdout)?;
-    }
+        None
+    };
+    let cfg = match cached_cfg {
+        Some(cfg) => cfg,
+        None => {
+            // Get the config as a string
+            let cfg_str = get_cfg()?;
+            // Create a raw config object and parse it fully
+            // We use `stdout` for printing warnings
+            // Any included files are merged in before parsing so everything's validated together
+            // The format is worked out from the file extension (or the contents if that doesn't help)
+            let cfg = Config::new_with_format(&cfg_str, ConfigFormat::detect(&cfg_path, &cfg_str))?
+                .load_includes(&cfg_path)?
+                .to_final(BONNIE_VERSION, stdout)?;
+            // If there was a stale cache, bring it back up to date
+            if cache_exists()? && !should_cache {
+                write_cache(&cfg, None)?;
+            }
+            cfg
+        }
+    };
 
     // Check if we're caching
     if should_cache {
@@ -104,10 +137,49 @@ fn core() -> Result<i32, String> {
         return Ok(0);
     }
 
+    if graph {
+        // The format can optionally be given before the command, DOT is the default
+        let mut graph_args = prog_args[1..].to_vec();
+        let format = match graph_args
+            .first()
+            .and_then(|arg| GraphFormat::from_flag(arg))
+        {
+            Some(format) => {
+                graph_args.remove(0);
+                format
+            }
+            None => GraphFormat::Dot,
+        };
+        let diagram = cfg.graph(&graph_args, format)?;
+        writeln!(stdout, "{}", diagram).expect("Failed to write graph.");
+        return Ok(0);
+    }
+
+    if all {
+        // Members are run by separate Bonnie processes, so we need to know where this one is
+        let bonnie_exe = match env::current_exe() {
+            Ok(bonnie_exe) => bonnie_exe,
+            Err(err) => {
+                return Err(format!(
+                    "Couldn't find the Bonnie executable to run workspace members with: '{}'.",
+                    err
+                ))
+            }
+        };
+        return run_workspace(&cfg, &cfg_path, &prog_args, &bonnie_exe, stdout);
+    }
+
     // Determine which command we're actually running
     let (command_to_run, command_name, relevant_args) = cfg.get_command_for_args(&prog_args)?;
     // Get the Bone (item in Bones execution runtime)
-    let bone = command_to_run.prepare(&command_name, &relevant_args, &cfg.default_shell)?;
+    let bone = command_to_run.prepare(&command_name, &relevant_args, &cfg, stdout)?;
+    // If the user only wants to know what would be run (including dependencies), we stop here
+    if dry_run {
+        bone.dry_run(&command_name, stdout)?;
+        return Ok(0);
+    }
+    // Make sure interrupts only stop the running command, so cleanup steps can still run afterwards
+    handle_interrupts()?;
     // Execute the Bone, getting its final exit code
     // We parse in `stdout` as the place to write command information, but that will only be done in testing
     let exit_code = bone.run(&command_name, verbose, stdout)?;
@@ -3,7 +3,31 @@
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::process::Command as OsCommand;
+use std::process::{Command as OsCommand, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Whether or not the user has interrupted Bonnie (e.g. with Ctrl-C)
+// The signal is delivered to the running command as well, so we just need to stop running further steps (except cleanup)
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Checks if the user has interrupted Bonnie, in which case no further steps should be started
+// Cleanup steps have to be run in full regardless, so interrupts are ignored while running them
+fn is_interrupted(in_cleanup: bool) -> bool {
+    !in_cleanup && INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// Stops Bonnie from being terminated immediately by interrupts so that cleanup steps can still be run afterwards
+// The command that's running will still receive the interrupt itself, and no further steps in ordered subcommands will be started
+pub fn handle_interrupts() -> Result<(), String> {
+    let res = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst));
+    match res {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!(
+            "The following error occurred while setting up Bonnie to handle interrupts: '{}'.",
+            err
+        )),
+    }
+}
 
 // This enables recursion of ordered subcommands (which would be the most complex use-case of Bonnie thus far)
 // This really represents (from Bonnie's perspective) a future for an exit code
@@ -11,6 +35,10 @@ use std::process::Command as OsCommand;
 pub enum Bone {
     Simple(BonesCore),
     Complex(BonesCommand),
+    // A series of commands run one after the other until one fails (used for dependencies, which come before the command itself)
+    Sequence(Vec<(String, Bone)>),
+    // A command whose `when` condition wasn't met (and that has no `else` command), this counts as a success
+    Skipped,
 }
 impl Bone {
     // Executes this command, returning its exit code
@@ -21,19 +49,215 @@ impl Bone {
         verbose: bool,
         output: &mut impl std::io::Write,
     ) -> Result<i32, String> {
+        let termination = self.run_after(name, None, false, verbose, output)?;
+        Ok(termination.exit_code())
+    }
+    // Executes this command as the step after the given one in an ordered chain (if there was one), returning how it terminated
+    // If this is being run as a cleanup step, interrupts won't stop it partway through
+    fn run_after(
+        &self,
+        name: &str,
+        prev: Option<&PreviousStep>,
+        in_cleanup: bool,
+        verbose: bool,
+        output: &mut impl std::io::Write,
+    ) -> Result<Termination, String> {
         match self {
             Bone::Simple(core) => {
                 // Execute the command core
-                let exit_code = core.execute(name, verbose, output)?;
-                // Return the exit code of the command sequence
-                Ok(exit_code)
+                let termination = core.execute(name, prev, verbose, output)?;
+                // Return the termination status of the command sequence
+                Ok(termination)
             }
             Bone::Complex(command) => {
                 // If it's complex and thus recursive, we depend on the Bones language parser
-                command.run(verbose, output)
+                command.run(prev, in_cleanup, verbose, output)
+            }
+            Bone::Sequence(steps) => {
+                let mut termination = Termination::Exited(0);
+                for (step_name, bone) in steps.iter() {
+                    termination = bone.run_after(step_name, None, in_cleanup, verbose, output)?;
+                    // Anything after a failure (or an interrupt) depends on it having worked, so we stop here
+                    if termination != Termination::Exited(0) || is_interrupted(in_cleanup) {
+                        break;
+                    }
+                }
+                Ok(termination)
+            }
+            Bone::Skipped => {
+                if verbose {
+                    writeln!(
+                        output,
+                        "Skipping command '{}' because its `when` condition isn't met.",
+                        name
+                    )
+                    .expect("Failed to write verbose information.");
+                }
+                Ok(Termination::Exited(0))
+            }
+        }
+    }
+    // Checks if any stage of this command inserts the arguments left over after its named ones (with `%%`)
+    pub fn uses_remaining_args(&self) -> bool {
+        match self {
+            Bone::Simple(core) => core.uses_remaining_args,
+            Bone::Complex(command) => command.cmds.values().any(|bone| bone.uses_remaining_args()),
+            Bone::Sequence(steps) => steps.iter().any(|(_, bone)| bone.uses_remaining_args()),
+            // Nothing at all is used, but arguments for a command that didn't need to run shouldn't be an error
+            Bone::Skipped => true,
+        }
+    }
+    // Gets the largest number of named arguments any stage of this command takes (including those of other scripts referenced in directives, which take their own)
+    pub fn num_args_used(&self) -> usize {
+        match self {
+            Bone::Simple(core) => core.num_args,
+            Bone::Complex(command) => command
+                .cmds
+                .values()
+                .map(|bone| bone.num_args_used())
+                .max()
+                .unwrap_or(0),
+            Bone::Sequence(steps) => steps
+                .iter()
+                .map(|(_, bone)| bone.num_args_used())
+                .max()
+                .unwrap_or(0),
+            Bone::Skipped => 0,
+        }
+    }
+    // Checks if any stage of this command inserts arguments without the quoting they need (because Bonnie doesn't know how to quote for its shell)
+    pub fn has_unquoted_args(&self) -> bool {
+        match self {
+            Bone::Simple(core) => core.has_unquoted_args,
+            Bone::Complex(command) => command.cmds.values().any(|bone| bone.has_unquoted_args()),
+            Bone::Sequence(steps) => steps.iter().any(|(_, bone)| bone.has_unquoted_args()),
+            Bone::Skipped => false,
+        }
+    }
+    // Writes what would be run for this command to the given output without running anything
+    // Ordered subcommands can't be fully resolved ahead of time (they depend on exit codes), so only their existence is noted
+    pub fn dry_run(&self, name: &str, output: &mut impl std::io::Write) -> Result<(), String> {
+        match self {
+            Bone::Simple(core) => {
+                let (executable, args) = core.get_invocation(name, None)?;
+                writeln!(
+                    output,
+                    "Would run '{}' as command '{}' with arguments '{:?}'.",
+                    name,
+                    executable,
+                    core.mask(&args)
+                )
+                .expect("Failed to write dry run information.");
+            }
+            Bone::Complex(_) => {
+                writeln!(output, "Would run the ordered subcommands of '{}' (which steps are run depends on their exit codes).", name)
+                    .expect("Failed to write dry run information.");
+            }
+            Bone::Sequence(steps) => {
+                let names: Vec<&str> = steps.iter().map(|(name, _)| name.as_str()).collect();
+                writeln!(
+                    output,
+                    "Would run these scripts in order: {}.",
+                    names.join(" -> ")
+                )
+                .expect("Failed to write dry run information.");
+                for (step_name, bone) in steps.iter() {
+                    bone.dry_run(step_name, output)?;
+                }
+            }
+            Bone::Skipped => {
+                writeln!(
+                    output,
+                    "Would skip '{}' because its `when` condition isn't met.",
+                    name
+                )
+                .expect("Failed to write dry run information.");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// How a command finished, which is more than just an exit code (it may have been killed by a signal, e.g. by the OOM killer)
+// This is kept in full while running ordered subcommands so directives can tell the two apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    // The command exited with this exit code
+    Exited(i32),
+    // The command was terminated by this signal (only possible on Unix)
+    Signaled(i32),
+    // The command's shell exited with `128 + signal` because something it ran was terminated by this signal
+    // POSIX shells do this whenever they survive their child (e.g. between stages, or in any compound command), so we only know this for them
+    ShellSignaled(i32),
+}
+impl Termination {
+    // Gets the exit code Bonnie should report for this
+    // Signals follow the shell convention of `128 + signal`
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Termination::Exited(exit_code) => *exit_code,
+            Termination::Signaled(signal) | Termination::ShellSignaled(signal) => 128 + signal,
+        }
+    }
+    // Gets how a child process terminated from its exit status, interpreting exit codes the way a POSIX shell reports signals if it was run in one
+    fn from_exit_status(exit_status: &ExitStatus, posix_shell: bool) -> Self {
+        // This will work on both Unix and Windows (and so theoretically any other weird OSes that make any sense at all)
+        match exit_status.code() {
+            // Signals are numbered from 1 to 64 at most
+            Some(exit_code) if posix_shell && (129..=192).contains(&exit_code) => {
+                Termination::ShellSignaled(exit_code - 128)
             }
+            Some(exit_code) => Termination::Exited(exit_code), // If we have an exit code, use it
+            None => match Self::get_signal(exit_status) {
+                Some(signal) => Termination::Signaled(signal), // If we don't, the process may have been killed by a signal
+                None if exit_status.success() => Termination::Exited(0), // If we know the command succeeded, return 0 (success code)
+                None => Termination::Exited(1), // If we don't know an exit code but we know that the command failed, return 1 (general error code)
+            },
         }
     }
+    #[cfg(unix)]
+    fn get_signal(exit_status: &ExitStatus) -> Option<i32> {
+        use std::os::unix::process::ExitStatusExt;
+        exit_status.signal()
+    }
+    // Processes can't be terminated by signals on other platforms
+    #[cfg(not(unix))]
+    fn get_signal(_exit_status: &ExitStatus) -> Option<i32> {
+        None
+    }
+}
+
+// Details of the last step that was run in an ordered chain, which can be interpolated into the next step as `%[prev.name]` and `%[prev.exit_code]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PreviousStep {
+    name: String,
+    termination: Termination,
+}
+impl PreviousStep {
+    // Interpolates the details of the previous step into the placeholders for them in the given command, giving the final command string
+    // If there was no previous step (e.g. this is the start of a chain), empty strings are interpolated instead
+    fn interpolate(prev: Option<&Self>, cmd: &[CommandPart]) -> String {
+        cmd.iter()
+            .map(|part| match (part, prev) {
+                (CommandPart::Text(text), _) => text.to_string(),
+                (CommandPart::PrevName, Some(prev)) => prev.name.to_string(),
+                (CommandPart::PrevExitCode, Some(prev)) => prev.termination.exit_code().to_string(),
+                (_, None) => String::new(),
+            })
+            .collect()
+    }
+}
+
+// A part of a command that's been interpolated as far as it can be before it's run
+// The details of the previous step in an ordered chain aren't known until then, so those are left as placeholders (they're never searched for in the final command, so they can't clobber anything that was interpolated)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CommandPart {
+    Text(String),
+    // `%[prev.name]`
+    PrevName,
+    // `%[prev.exit_code]`
+    PrevExitCode,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -43,26 +267,43 @@ pub struct BonesCommand {
     cmds: HashMap<String, Bone>,
     // The directive from of how to run the commands (written as per Bones' specification)
     directive: BonesDirective,
+    // The names of commands that will always be run after the directive, however it ended (including by errors and interrupts)
+    finally: Vec<String>,
 }
 impl BonesCommand {
     // This creates a full Bones command
     // This is used when actual logic is given by the user (ordered subcommands)
-    pub fn new(directive: &BonesDirective, cmds: HashMap<String, Bone>) -> Self {
+    pub fn new(
+        directive: &BonesDirective,
+        cmds: HashMap<String, Bone>,
+        finally: Vec<String>,
+    ) -> Self {
         Self {
             directive: directive.clone(),
             cmds,
+            finally,
         }
     }
     // Runs a Bones command by evaluating the directive itself and calling commands in sequence recursively
     // Currently, the logic of the Bones language lives here
-    fn run(&self, verbose: bool, output: &mut impl std::io::Write) -> Result<i32, String> {
+    // This takes the step before this command if it's part of a larger ordered chain
+    fn run(
+        &self,
+        prev: Option<&PreviousStep>,
+        in_cleanup: bool,
+        verbose: bool,
+        output: &mut impl std::io::Write,
+    ) -> Result<Termination, String> {
         // This system is highly recursive, so everything is done in this function for progressively less complex directives
+        // The step run before this directive is passed through so its details can be interpolated just before the next command is spawned
         fn run_for_directive(
             directive: &BonesDirective,
             cmds: &HashMap<String, Bone>,
+            prev: Option<&PreviousStep>,
+            in_cleanup: bool,
             verbose: bool,
             output: &mut impl std::io::Write,
-        ) -> Result<i32, String> {
+        ) -> Result<Termination, String> {
             // Get the token, which names the command we'll be running
             let command_name = &directive.0;
             // Now get the corresponding Bone if it exists
@@ -73,31 +314,74 @@ impl BonesCommand {
             };
             // Now execute it and get the exit code (this may recursively call this function if ordered subcommands are nested, but that dcoesn't matter)
             // Bonnie treats all command cores as futures for an exit code, we don't care about any side effects (printing, server execution, etc.)
-            let exit_code = bone.run(command_name, verbose, output)?;
-            // Iterate over the conditions given and check if any of them match that exit code
+            let termination = bone.run_after(command_name, prev, in_cleanup, verbose, output)?;
+            // If the user interrupted that command, we shouldn't start anything else
+            if is_interrupted(in_cleanup) {
+                return Ok(termination);
+            }
+            // Any further steps will be run after this one
+            let this_step = PreviousStep {
+                name: command_name.to_string(),
+                termination,
+            };
+            // Iterate over the conditions given and check if any of them match how that command terminated
             // We'll run the first one that does (even if more do after that)
             // TODO document the above behaviour
-            let mut final_exit_code = exit_code;
+            let mut final_termination = termination;
             for (operator, directive) in directive.1.iter() {
-                if operator.matches(&exit_code) {
+                if operator.matches(&termination) {
                     // An operator has matched, check if it has an associated directive
-                    final_exit_code = match directive {
+                    final_termination = match directive {
                         // If it does, run that and get its exit code
-                        Some(directive) => run_for_directive(directive, cmds, verbose, output)?,
-                        // If not, return the exit code we just got above
-                        None => exit_code,
+                        Some(directive) => run_for_directive(
+                            directive,
+                            cmds,
+                            Some(&this_step),
+                            in_cleanup,
+                            verbose,
+                            output,
+                        )?,
+                        // If not, return the termination we just got above
+                        None => termination,
                     };
                 }
             }
 
-            // All nestings have resolved to one exit code, we return it
-            Ok(final_exit_code)
+            // All nestings have resolved to one termination status, we return it
+            Ok(final_termination)
         }
 
         // Begin the recursion on this top-level directive
-        // This will eventually return the exit code from the lowest level of recursion, which we return
-        let exit_code = run_for_directive(&self.directive, &self.cmds, verbose, output)?;
-        Ok(exit_code)
+        // This will eventually return the exit code from the lowest level of recursion
+        // We don't use `?` here because cleanup steps have to run even if this fails
+        let res = run_for_directive(
+            &self.directive,
+            &self.cmds,
+            prev,
+            in_cleanup,
+            verbose,
+            output,
+        );
+        // Now run every cleanup step, even if earlier ones fail
+        // The original result is preserved unless cleanup itself fails, in which case we return the first such failure
+        let mut cleanup_failure = None;
+        for command_name in self.finally.iter() {
+            // Preparation guarantees that every cleanup step has a corresponding Bone
+            let bone = match self.cmds.get(command_name) {
+                Some(bone) => bone,
+                None => return Err(format!("Error in executing Bones directive: cleanup step '{}' not found. This is probably a typo in your Bonnie configuration.", command_name)),
+            };
+            // Cleanup steps run in full even after an interrupt (including any ordered subcommands they have)
+            let cleanup_res = bone.run_after(command_name, None, true, verbose, output);
+            if cleanup_failure.is_none() && !matches!(cleanup_res, Ok(Termination::Exited(0))) {
+                cleanup_failure = Some(cleanup_res);
+            }
+        }
+
+        match cleanup_failure {
+            Some(cleanup_res) => cleanup_res,
+            None => res,
+        }
     }
 }
 
@@ -105,6 +389,29 @@ impl BonesCommand {
 // This maps the command to run to a set of conditions as to how to proceed based on its exit code
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct BonesDirective(String, HashMap<BonesOperator, Option<BonesDirective>>);
+impl BonesDirective {
+    // Gets the name of the subcommand this directive will run first
+    pub fn command_name(&self) -> &str {
+        &self.0
+    }
+    // Gets the conditions on that subcommand's exit code and the directives they lead to
+    pub fn conditions(&self) -> &HashMap<BonesOperator, Option<BonesDirective>> {
+        &self.1
+    }
+    // Gets the names of all the subcommands this directive could run (each only once)
+    pub fn command_names(&self) -> Vec<&str> {
+        let mut names = vec![self.0.as_str()];
+        for directive in self.1.values().flatten() {
+            for name in directive.command_names() {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+
+        names
+    }
+}
 // This is used for direct parsing, before we've had a chance to handle the operators
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 struct RawBonesDirective(String, HashMap<String, Option<RawBonesDirective>>);
@@ -146,6 +453,10 @@ pub enum BonesOperator {
     Success,
     // The requirement for command failure (an alias for `NotExitCode(0)`)
     Failure,
+    // The requirement for the command to have been terminated by a specific signal (e.g. `Signal(9)` for `SIGKILL`)
+    Signal(i32),
+    // The requirement for the command to have been terminated by any signal
+    Signaled,
     // Matches if any contained operators match (or statement)
     Union(Vec<BonesOperator>),
     // Matches if all contained operators match (and statement)
@@ -153,22 +464,40 @@ pub enum BonesOperator {
     Intersection(Vec<BonesOperator>),
 }
 impl BonesOperator {
-    // Checks if the given exit code matches this operator
-    fn matches(&self, exit_code: &i32) -> bool {
+    // Checks if the given termination status of a command matches this operator
+    // Commands terminated by signals have no exit code, so they'll never match `Success` or a specific exit code
+    // A shell reporting that something it ran was terminated by a signal still exited with a code though, so that matches both
+    fn matches(&self, termination: &Termination) -> bool {
+        fn has_exit_code(termination: &Termination, exit_code: i32) -> bool {
+            match termination {
+                Termination::Exited(_) | Termination::ShellSignaled(_) => {
+                    termination.exit_code() == exit_code
+                }
+                Termination::Signaled(_) => false,
+            }
+        }
         // This can be recursive due to the `Union` an d`Intersection` variants
-        fn matches(exit_code: &i32, variant: &BonesOperator) -> bool {
+        fn matches(termination: &Termination, variant: &BonesOperator) -> bool {
             // Go through each different type of operator possible
             match variant {
-                BonesOperator::Success => *exit_code == 0,
-                BonesOperator::Failure => *exit_code != 0,
-                BonesOperator::ExitCode(comparison) => exit_code == comparison,
-                BonesOperator::NotExitCode(comparison) => exit_code != comparison,
+                BonesOperator::Success => *termination == Termination::Exited(0),
+                BonesOperator::Failure => *termination != Termination::Exited(0),
+                BonesOperator::ExitCode(comparison) => has_exit_code(termination, *comparison),
+                BonesOperator::NotExitCode(comparison) => !has_exit_code(termination, *comparison),
+                BonesOperator::Signal(comparison) => matches!(
+                    termination,
+                    Termination::Signaled(signal) | Termination::ShellSignaled(signal) if signal == comparison
+                ),
+                BonesOperator::Signaled => matches!(
+                    termination,
+                    Termination::Signaled(_) | Termination::ShellSignaled(_)
+                ),
                 BonesOperator::Any => true,
                 BonesOperator::None => false,
                 BonesOperator::Union(operators) => {
                     let mut is_match = false;
                     for operator in operators {
-                        let op_matches = operator.matches(exit_code);
+                        let op_matches = operator.matches(termination);
                         // We only need one of them to be true
                         if op_matches {
                             is_match = true;
@@ -180,7 +509,7 @@ impl BonesOperator {
                 BonesOperator::Intersection(operators) => {
                     let mut is_match = false;
                     for operator in operators {
-                        let op_matches = operator.matches(exit_code);
+                        let op_matches = operator.matches(termination);
                         // We only need one of them to be false (aka. all of them have to be true)
                         is_match = op_matches;
                         if !op_matches {
@@ -192,7 +521,7 @@ impl BonesOperator {
             }
         }
 
-        matches(exit_code, self)
+        matches(termination, self)
     }
     // Parses a string operator given in a directive string into a fully-fledged variant
     fn parse_str(raw_operator: &str) -> Result<Self, String> {
@@ -211,11 +540,12 @@ impl BonesOperator {
                 };
                 BonesOperator::NotExitCode(exit_code)
             }
-            // The next four are simple because they have no attached data
+            // The next five are simple because they have no attached data
             "Any" => BonesOperator::Any,
             "None" => BonesOperator::None,
             "Success" => BonesOperator::Success,
             "Failure" => BonesOperator::Failure,
+            "Signaled" => BonesOperator::Signaled,
             // These require recursion
             _ if raw_operator.contains('|') => {
                 let parts: Vec<&str> = raw_operator.split('|').collect();
@@ -235,6 +565,15 @@ impl BonesOperator {
                 }
                 BonesOperator::Intersection(operators)
             }
+            // This has to come after the recursive operators, otherwise we'd catch something like `Signal(9)|1`
+            _ if raw_operator.starts_with("Signal(") && raw_operator.ends_with(')') => {
+                let signal_str = &raw_operator["Signal(".len()..raw_operator.len() - 1];
+                let signal = match signal_str.parse::<i32>() {
+                    Ok(signal) => signal,
+                    Err(_) => return Err(format!("Couldn't parse signal number as 32-bit integer from `Signal` operator invocation '{}'.", raw_operator))
+                };
+                BonesOperator::Signal(signal)
+            }
             _ => {
                 return Err(format!(
                     "Unrecognized operator '{}' in Bones directive.",
@@ -246,21 +585,68 @@ impl BonesOperator {
         Ok(operator)
     }
 }
+// This writes operators back out in the same syntax they're parsed from in directive strings
+impl std::fmt::Display for BonesOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Joins a series of operators with the given separator (for `Union` and `Intersection`)
+        fn join(operators: &[BonesOperator], separator: &str) -> String {
+            operators
+                .iter()
+                .map(|operator| operator.to_string())
+                .collect::<Vec<String>>()
+                .join(separator)
+        }
+
+        match self {
+            BonesOperator::ExitCode(exit_code) => write!(f, "{}", exit_code),
+            BonesOperator::NotExitCode(exit_code) => write!(f, "!{}", exit_code),
+            BonesOperator::Any => write!(f, "Any"),
+            BonesOperator::None => write!(f, "None"),
+            BonesOperator::Success => write!(f, "Success"),
+            BonesOperator::Failure => write!(f, "Failure"),
+            BonesOperator::Signal(signal) => write!(f, "Signal({})", signal),
+            BonesOperator::Signaled => write!(f, "Signaled"),
+            BonesOperator::Union(operators) => write!(f, "{}", join(operators, "|")),
+            BonesOperator::Intersection(operators) => write!(f, "{}", join(operators, "+")),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct BonesCore {
-    pub cmd: String,        // All the stages are joined by the delimiter
-    pub shell: Vec<String>, // Vector of executable and arguments thereto
+    pub cmd: Vec<CommandPart>,     // All the stages are joined by the delimiter
+    pub shell: Vec<String>,        // Vector of executable and arguments thereto
+    pub uses_remaining_args: bool, // Whether or not any stage inserted the arguments left over after the named ones (with `%%`)
+    pub has_unquoted_args: bool,   // Whether or not any arguments couldn't be quoted for the shell
+    pub num_args: usize, // The number of named arguments this takes from those given to the program
+    pub posix_shell: bool, // Whether or not this is run in a POSIX shell (which reports signals that terminate what it runs as exit codes)
+    pub secrets: Vec<String>, // Values that must never be printed (they're replaced with `***` in anything written to the output)
+    pub env: HashMap<String, String>, // Environment variables from the script's own environment variable files, which are only set for its process
 }
 impl BonesCore {
-    fn execute(
+    // Masks any secrets in the given arguments so they can be printed
+    fn mask(&self, args: &[String]) -> Vec<String> {
+        // Longer secrets go first so one that contains another is still masked completely
+        let mut secrets: Vec<&String> = self.secrets.iter().collect();
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        args.iter()
+            .map(|arg| {
+                secrets.iter().fold(arg.to_string(), |arg, secret| {
+                    arg.replace(secret.as_str(), "***")
+                })
+            })
+            .collect()
+    }
+    // Gets the executable and arguments that will actually be run for this command
+    // This takes the previous step if this is being run as part of an ordered chain, the details of which are interpolated now
+    fn get_invocation(
         &self,
         name: &str,
-        verbose: bool,
-        output: &mut impl std::io::Write,
-    ) -> Result<i32, String> {
+        prev: Option<&PreviousStep>,
+    ) -> Result<(String, Vec<String>), String> {
+        let cmd = PreviousStep::interpolate(prev, &self.cmd);
         // Get the executable from the shell (the first element)
-        let executable = self.shell.get(0);
+        let executable = self.shell.first();
         let executable = match executable {
             // If the shell is not universal to all stages, we return an error
             // We should not have to interpolate anything into the executable
@@ -274,14 +660,28 @@ impl BonesCore {
         let args: Vec<String> = match args {
             Some(args) => args
                 .iter()
-                .map(|part| part.replace("{COMMAND}", &self.cmd))
+                .map(|part| part.replace("{COMMAND}", &cmd))
                 .collect(),
             // If there are no arguments, we really don't care, shells can be as weird as they want
             None => Vec::new(),
         };
+
+        Ok((executable.to_string(), args))
+    }
+    // This takes the previous step if this is being run as part of an ordered chain, the details of which are interpolated now
+    fn execute(
+        &self,
+        name: &str,
+        prev: Option<&PreviousStep>,
+        verbose: bool,
+        output: &mut impl std::io::Write,
+    ) -> Result<Termination, String> {
+        let (executable, args) = self.get_invocation(name, prev)?;
+        // Secrets are only masked in what we print, the child process gets the real command
+        let masked_args = self.mask(&args);
         // If we're in debug, write details about the command to the given output (technical)
         if cfg!(debug_assertions) {
-            writeln!(output, "{}, {:?}", executable, args)
+            writeln!(output, "{}, {:?}", executable, masked_args)
                 .expect("Failed to write technical information.");
         }
         // If the user wants it, write the actual command we'll run to the given output
@@ -289,12 +689,15 @@ impl BonesCore {
             writeln!(
                 output,
                 "Running command '{}' with arguments '{:?}'.",
-                executable, args
+                executable, masked_args
             )
             .expect("Failed to write verbose information.");
         }
         // Prepare the child process
-        let child = OsCommand::new(&executable).args(args).spawn();
+        let child = OsCommand::new(executable)
+            .args(args)
+            .envs(&self.env)
+            .spawn();
 
         // The child must be mutable so we can wait for it to finish later
         let mut child = match child {
@@ -318,13 +721,11 @@ impl BonesCore {
             )
         };
 
-        // We now need to pass that exit code through so Bonnie can terminate with it (otherwise `&&` chaining doesn't work as expected, etc.)
-        // This will work on both Unix and Windows (and so theoretically any other weird OSes that make any sense at all)
-        Ok(match exit_status.code() {
-            Some(exit_code) => exit_code,       // If we have an exit code, use it
-            None if exit_status.success() => 0, // If we don't, but we know the command succeeded, return 0 (success code)
-            None => 1, // If we don't know an exit code but we know that the command failed, return 1 (general error code)
-        })
+        // We now need to pass how the command terminated through so Bonnie can terminate with it (otherwise `&&` chaining doesn't work as expected, etc.)
+        Ok(Termination::from_exit_status(
+            &exit_status,
+            self.posix_shell,
+        ))
     }
 }
 
@@ -333,10 +734,9 @@ impl BonesCore {
 // This function basically interprets a miniature programming language
 // Right now, this is quite slow due to its extensive use of RegEx, any ideas to speed it up would be greatly appreciated!
 pub fn parse_directive_str(directive_str: &str) -> Result<BonesDirective, String> {
-    let directive_json: String;
     // Check if we have the alternative super-simple form (just one command, rare but easy to parse)
-    if !directive_str.contains('{') {
-        directive_json = "[\"".to_string() + directive_str + "\", {}]"
+    let directive_json = if !directive_str.contains('{') {
+        "[\"".to_string() + directive_str + "\", {}]"
     } else {
         // We transform the directive string into compliant JSON with a series of substitutions
         // Execute non-regex substitutions
@@ -352,8 +752,8 @@ pub fn parse_directive_str(directive_str: &str) -> Result<BonesDirective, String
         // Execute each of those substitutions
         let stage2 = re1.replace_all(&stage1, sub1);
         let stage3 = re2.replace_all(&stage2, sub2);
-        directive_json = re3.replace_all(&stage3, sub3).to_string();
-    }
+        re3.replace_all(&stage3, sub3).to_string()
+    };
     // Now we can deserialize that directly using Serde
     let raw_directive = serde_json::from_str::<RawBonesDirective>(&directive_json);
     let raw_directive = match raw_directive {
@@ -1,10 +1,32 @@
 use crate::version::BONNIE_VERSION;
 use crate::{raw_schema, schema};
+use serde::{Deserialize, Serialize};
 use std::env;
 use std::fs;
+use std::time::UNIX_EPOCH;
 
 // This can be changed by the user with the `BONNIE_CACHE` environment variable
+#[cfg(not(feature = "binary-cache"))]
 pub const DEFAULT_BONNIE_CACHE_PATH: &str = "./.bonnie.cache.json";
+#[cfg(feature = "binary-cache")]
+pub const DEFAULT_BONNIE_CACHE_PATH: &str = "./.bonnie.cache.bin";
+
+// Binary caches start with these magic bytes followed by the format version (a little-endian `u32`) and the version of Bonnie that wrote
+// them (NUL-terminated), so we can tell them apart from JSON caches and from binary caches written by anything with a different layout
+// The binary format isn't self-describing, so a cache is only ever read by the same version of Bonnie that wrote it
+// The format version only needs to be bumped if `schema::Config` (or anything it contains) changes without the version of Bonnie changing
+const BINARY_CACHE_MAGIC: &[u8; 8] = b"BONNIE\0C";
+#[cfg(feature = "binary-cache")]
+const BINARY_CACHE_FORMAT_VERSION: u32 = 2;
+
+// Gets the part of the header of binary caches that comes after the magic bytes, which must match exactly for a cache to be read
+#[cfg(feature = "binary-cache")]
+fn get_binary_cache_version() -> Vec<u8> {
+    let mut version = BINARY_CACHE_FORMAT_VERSION.to_le_bytes().to_vec();
+    version.extend_from_slice(BONNIE_VERSION.as_bytes());
+    version.push(0);
+    version
+}
 
 // Gets the path to the cache file based on given environment variables
 // This will return an error if the `BONNIE_CACHE` environment variable is set, but is invalid
@@ -18,6 +40,88 @@ fn get_cache_path() -> Result<String, String> {
     }
 }
 
+// A file the cached configuration was built from, recorded so we can tell if it's changed since
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct SourceFile {
+    path: String,
+    mtime: Option<(u64, u32)>, // Seconds and nanoseconds since the Unix epoch, `None` if the file didn't exist
+    hash: Option<u64>,         // `None` if the file didn't exist
+}
+impl SourceFile {
+    fn new(path: &str) -> Self {
+        let contents = fs::read(path).ok();
+        Self {
+            path: path.to_string(),
+            mtime: get_mtime(path),
+            hash: contents.map(|contents| hash(&contents)),
+        }
+    }
+    // Checks if this file is the same as it was when the cache was made
+    // If the modification time hasn't changed, we don't bother reading the file (that's the whole point of the cache)
+    fn is_fresh(&self) -> bool {
+        let mtime = get_mtime(&self.path);
+        if mtime.is_some() && mtime == self.mtime {
+            return true;
+        }
+        // The file may have just been touched, so we check if its contents actually changed
+        fs::read(&self.path).ok().map(|contents| hash(&contents)) == self.hash
+    }
+}
+
+// A glob pattern files were included with, recorded with the files it matched so we can tell if any have been added or removed since
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct IncludePattern {
+    pattern: String,
+    matches: Vec<String>,
+}
+impl IncludePattern {
+    fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            matches: expand_pattern(pattern),
+        }
+    }
+    // Checks if this pattern still matches exactly the same files as it did when the cache was made
+    fn is_fresh(&self) -> bool {
+        expand_pattern(&self.pattern) == self.matches
+    }
+}
+// Gets the paths of all the files the given glob pattern matches (in the order they'd be included)
+// Errors are ignored, if the pattern can't be expanded the config will fail to be rebuilt and show them properly
+fn expand_pattern(pattern: &str) -> Vec<String> {
+    match glob::glob(pattern) {
+        Ok(paths) => paths
+            .filter_map(|path| path.ok())
+            .map(|path| path.to_string_lossy().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// What's actually written to the cache file
+#[derive(Serialize, Deserialize)]
+struct Cache {
+    sources: Vec<SourceFile>,
+    include_patterns: Vec<IncludePattern>,
+    cfg: schema::Config,
+}
+
+fn get_mtime(path: &str) -> Option<(u64, u32)> {
+    let mtime = fs::metadata(path).ok()?.modified().ok()?;
+    let since_epoch = mtime.duration_since(UNIX_EPOCH).ok()?;
+    Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+// A 64-bit FNV-1a hash, which is fast and, unlike the standard library's hasher, stable across Rust versions
+fn hash(contents: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in contents {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
 // Serializes the given parsed configuration into a JSON string and write it to disk to speed up future execution
 // This takes around 100ms on an old i7 for the testing file
 // We extract the path for testing (which needs to use a temporary file)
@@ -26,26 +130,74 @@ pub fn cache(
     output: &mut impl std::io::Write,
     raw_cache_path: Option<&str>,
 ) -> Result<(), String> {
+    let cache_path = write_cache(cfg, raw_cache_path)?;
+
+    writeln!(
+        output,
+        "Your Bonnie configuration has been successfully cached to '{}'! This will be used to speed up future execution, and will be rebuilt automatically whenever your configuration (or any file it includes or loads environment variables from) changes.",
+        cache_path
+    ).expect("Failed to write caching message.");
+    Ok(())
+}
+
+// The formats the cache can be written in (either can always be read if this build supports it)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheFormat {
+    Json,
+    #[cfg(feature = "binary-cache")]
+    Binary,
+}
+impl Default for CacheFormat {
+    // Builds with binary caching enabled use it by default
+    fn default() -> Self {
+        #[cfg(feature = "binary-cache")]
+        return Self::Binary;
+        #[cfg(not(feature = "binary-cache"))]
+        return Self::Json;
+    }
+}
+
+// Writes the cache without printing anything, returning where it was written to
+// This is used directly when the cache is transparently rebuilt
+pub fn write_cache(cfg: &schema::Config, raw_cache_path: Option<&str>) -> Result<String, String> {
+    write_cache_as(cfg, raw_cache_path, CacheFormat::default())
+}
+
+// Writes the cache in the given format, which is only really useful for comparing formats (e.g. in benchmarks)
+pub fn write_cache_as(
+    cfg: &schema::Config,
+    raw_cache_path: Option<&str>,
+    format: CacheFormat,
+) -> Result<String, String> {
     let cache_path = match raw_cache_path {
         Some(cache_path) => cache_path.to_string(),
         None => get_cache_path()?,
     };
-    let cache_str = serde_json::to_string(cfg);
-    let cache_str = match cache_str {
-        Ok(cache_str) => cache_str,
-        Err(err) => return Err(format!("The following error occurred while attempting to cache your parsed Bonnie configuration: '{}'.", err))
+    // Record everything the config was built from so we can tell when the cache goes stale
+    let sources = cfg
+        .sources
+        .iter()
+        .cloned()
+        .chain(cfg.get_all_env_files())
+        .map(|path| SourceFile::new(&path))
+        .collect();
+    let include_patterns = cfg
+        .include_patterns
+        .iter()
+        .map(|pattern| IncludePattern::new(pattern))
+        .collect();
+    let cache = Cache {
+        sources,
+        include_patterns,
+        cfg: cfg.clone(),
     };
-    let res = fs::write(&cache_path, cache_str);
+    let cache_bytes = encode_cache(&cache, format)?;
+    let res = fs::write(&cache_path, cache_bytes);
     if let Err(err) = res {
         return Err(format!("The following error occurred while attempting to write your cached Bonnie configuration to '{}': '{}'.", &cache_path, err));
     }
 
-    writeln!(
-        output,
-        "Your Bonnie configuration has been successfully cached to '{}'! This will be used to speed up future execution. Please note that this cache will NOT be updated until you explicitly run `bonnie -c` again.",
-        cache_path
-    ).expect("Failed to write caching message.");
-    Ok(())
+    Ok(cache_path)
 }
 
 pub fn cache_exists() -> Result<bool, String> {
@@ -53,31 +205,75 @@ pub fn cache_exists() -> Result<bool, String> {
     Ok(exists)
 }
 
-// This does NOT attempt to check if the cache is out of date for performance
-// The user must manually recache
+// This checks if any of the files the cache was built from have changed (or if files have been added or removed where they were included from), and returns `None` if they have (or if the cache is from an older
+// version of Bonnie and can't be read), in which case the caller should rebuild it from the source configuration
 pub fn load_from_cache(
     output: &mut impl std::io::Write,
     raw_cache_path: Option<&str>,
-) -> Result<schema::Config, String> {
+) -> Result<Option<schema::Config>, String> {
     let cache_path = match raw_cache_path {
         Some(cache_path) => cache_path.to_string(),
         None => get_cache_path()?,
     };
-    let cfg_str = fs::read_to_string(&cache_path);
-    let cfg_str = match cfg_str {
-        Ok(cfg_str) => cfg_str,
+    let cache_bytes = fs::read(&cache_path);
+    let cache_bytes = match cache_bytes {
+        Ok(cache_bytes) => cache_bytes,
         Err(err) => return Err(format!("The following error occurred while attempting to read your cached Bonnie configuration at '{}': '{}'.", &cache_path, err))
     };
 
-    let cfg = serde_json::from_str::<schema::Config>(&cfg_str);
-    let cfg = match cfg {
-        Ok(cfg) => cfg,
-        Err(err) => return Err(format!("The following error occurred while attempting to parse your cached Bonnie configuration at '{}': '{}'. If this persists, you can recache with `bonnie -c`.", &cache_path, err))
+    let cache = match decode_cache(&cache_bytes) {
+        Some(cache) => cache,
+        None => return Ok(None),
     };
+    if !cache.sources.iter().all(|source| source.is_fresh())
+        || !cache
+            .include_patterns
+            .iter()
+            .all(|pattern| pattern.is_fresh())
+    {
+        return Ok(None);
+    }
+    let cfg = cache.cfg;
     // Check the version
     raw_schema::Config::parse_version_against_current(&cfg.version, BONNIE_VERSION, output)?;
     // Load the environment variable files
     raw_schema::Config::load_env_files(Some(cfg.env_files.clone()))?;
 
-    Ok(cfg)
+    Ok(Some(cfg))
+}
+
+// Serializes the cache in the given format, binary caches getting a header of magic bytes and the versions they were written with
+fn encode_cache(cache: &Cache, format: CacheFormat) -> Result<Vec<u8>, String> {
+    let cache_bytes = match format {
+        CacheFormat::Json => serde_json::to_vec(cache).map_err(|err| err.to_string()),
+        #[cfg(feature = "binary-cache")]
+        CacheFormat::Binary => {
+            let mut cache_bytes = BINARY_CACHE_MAGIC.to_vec();
+            cache_bytes.extend_from_slice(&get_binary_cache_version());
+            bincode::serialize_into(&mut cache_bytes, cache)
+                .map(|_| cache_bytes)
+                .map_err(|err| err.to_string())
+        }
+    };
+    match cache_bytes {
+        Ok(cache_bytes) => Ok(cache_bytes),
+        Err(err) => Err(format!("The following error occurred while attempting to cache your parsed Bonnie configuration: '{}'.", err))
+    }
+}
+
+// Deserializes a cache in either format, returning `None` if it can't be read by this build of Bonnie (so it should be rebuilt)
+fn decode_cache(cache_bytes: &[u8]) -> Option<Cache> {
+    match cache_bytes.strip_prefix(BINARY_CACHE_MAGIC) {
+        Some(versioned_bytes) => decode_binary_cache(versioned_bytes),
+        None => serde_json::from_slice(cache_bytes).ok(),
+    }
+}
+#[cfg(not(feature = "binary-cache"))]
+fn decode_binary_cache(_versioned_bytes: &[u8]) -> Option<Cache> {
+    None
+}
+#[cfg(feature = "binary-cache")]
+fn decode_binary_cache(versioned_bytes: &[u8]) -> Option<Cache> {
+    let cache_bytes = versioned_bytes.strip_prefix(&get_binary_cache_version()[..])?;
+    bincode::deserialize(cache_bytes).ok()
 }
@@ -0,0 +1,95 @@
+// This file contains the logic for pointing errors in configuration files at the exact place they occurred, with a snippet of the source (like `rustc` does)
+// Spans are only tracked for TOML, so errors in other formats are given without snippets
+
+use crate::raw_schema::ConfigFormat;
+use std::ops::Range;
+use toml_edit::{ImDocument, Item, TableLike};
+
+// Finds the locations of keys in the source of a configuration file
+pub struct SourceLocator {
+    // The name of the file the source came from (used in the rendered location)
+    name: String,
+    doc: Option<ImDocument<String>>,
+}
+impl SourceLocator {
+    // Creates a locator for the given source in the given format
+    // If the source can't be parsed with spans, errors just won't get snippets
+    pub fn new(name: &str, src: &str, format: ConfigFormat) -> Self {
+        let doc = match format {
+            ConfigFormat::Toml => ImDocument::parse(src.to_string()).ok(),
+            _ => None,
+        };
+        Self {
+            name: name.to_string(),
+            doc,
+        }
+    }
+    // Creates a locator that can't locate anything (for when there's no source)
+    pub fn empty() -> Self {
+        Self {
+            name: String::new(),
+            doc: None,
+        }
+    }
+    // Adds a snippet pointing at the key at the given path (e.g. `["scripts", "deploy", "cmd"]`) to the given error message
+    // If that key can't be found, this will point at the closest parent that can be (or add nothing if there isn't one)
+    pub fn error_at(&self, path: &[String], msg: String) -> String {
+        let doc = match &self.doc {
+            Some(doc) => doc,
+            None => return msg,
+        };
+        let span = (1..=path.len())
+            .rev()
+            .find_map(|len| locate(doc.as_table(), &path[..len]));
+        match span {
+            Some(span) => format!("{}\n{}", msg, render_snippet(&self.name, doc.raw(), span)),
+            None => msg,
+        }
+    }
+}
+
+// Gets the span of the key at the given path in the given table
+fn locate(table: &dyn TableLike, path: &[String]) -> Option<Range<usize>> {
+    let (key, item) = table.get_key_value(path.first()?)?;
+    match path.len() {
+        1 => key.span(),
+        _ => match item {
+            Item::Table(table) => locate(table, &path[1..]),
+            Item::Value(value) => locate(value.as_inline_table()?, &path[1..]),
+            _ => None,
+        },
+    }
+}
+
+// Renders a snippet of the source around the given span, with the location and carets underneath the spanned text
+fn render_snippet(name: &str, src: &str, span: Range<usize>) -> String {
+    let line_start = src[..span.start].rfind('\n').map_or(0, |idx| idx + 1);
+    let line_end = src[span.start..]
+        .find('\n')
+        .map_or(src.len(), |idx| span.start + idx);
+    let line = &src[line_start..line_end];
+    let line_num = src[..span.start].matches('\n').count() + 1;
+    let col_num = src[line_start..span.start].chars().count() + 1;
+    // Spans over multiple lines are only underlined on the first one
+    let underline_len = src[span.start..span.end.min(line_end)]
+        .chars()
+        .count()
+        .max(1);
+    let gutter = " ".repeat(line_num.to_string().len());
+    // Tabs are kept so the carets line up however wide they're displayed
+    let padding: String = src[line_start..span.start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    format!(
+        "{gutter}--> {name}:{line_num}:{col_num}\n{gutter} |\n{line_num} | {line}\n{gutter} | {padding}{underline}",
+        gutter = gutter,
+        name = name,
+        line_num = line_num,
+        col_num = col_num,
+        line = line,
+        padding = padding,
+        underline = "^".repeat(underline_len),
+    )
+}
@@ -0,0 +1,91 @@
+// This file contains the logic for loading environment variable files, both global ones (`env_files` at the top level) and those for individual scripts
+// The precedence is: the real environment, then the files of the script being run, then the global files (earlier global files beat later ones, later script files beat earlier ones)
+// Entries starting with `?` (e.g. `?.env.local`) are optional, and are skipped if they don't exist
+
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::sync::Mutex;
+
+// The names of the environment variables that were set by global files (rather than being in the real environment), which script files can override
+static SET_BY_GLOBAL_FILES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Loads the given global environment variable files into Bonnie's own environment, so they're available to every script
+// Nothing already in the environment is overridden
+pub fn load_global_env_files(env_files: &[String]) -> Result<(), String> {
+    for env_file in env_files.iter() {
+        let vars = match read_env_file(env_file)? {
+            Some(vars) => vars,
+            None => continue,
+        };
+        for (name, value) in vars {
+            if env::var_os(&name).is_none() {
+                env::set_var(&name, value);
+                SET_BY_GLOBAL_FILES
+                    .lock()
+                    .expect("Failed to record environment variables set by files.")
+                    .push(name);
+            }
+        }
+    }
+
+    Ok(())
+}
+// Loads the environment variable files of a single script
+// These aren't loaded into Bonnie's own environment (so they can't leak into other scripts), this returns the variables that should be set for the script (anything that isn't overridden by the real environment)
+pub fn load_script_env_files(env_files: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut script_vars = HashMap::new();
+    for env_file in env_files.iter() {
+        if let Some(vars) = read_env_file(env_file)? {
+            // Later files take precedence over earlier ones, so parents' files can be overridden by their subcommands'
+            script_vars.extend(vars);
+        }
+    }
+    let set_by_global_files = SET_BY_GLOBAL_FILES
+        .lock()
+        .expect("Failed to read environment variables set by files.");
+    script_vars.retain(|name, _| env::var_os(name).is_none() || set_by_global_files.contains(name));
+
+    Ok(script_vars)
+}
+// Makes the given environment variable file entry relative to the given directory (keeping it optional if it was)
+pub fn rebase_env_file(dir: &Path, env_file: &str) -> String {
+    let (path, optional) = parse_entry(env_file);
+    let rebased = dir.join(path).to_string_lossy().to_string();
+    match optional {
+        true => format!("?{}", rebased),
+        false => rebased,
+    }
+}
+
+// Splits an entry into its path and whether or not it's optional
+fn parse_entry(env_file: &str) -> (&str, bool) {
+    match env_file.strip_prefix('?') {
+        Some(path) => (path, true),
+        None => (env_file, false),
+    }
+}
+// Reads the variables in the environment variable file of the given entry, in order
+// This returns `None` if the file is optional and doesn't exist
+// The iterator is deprecated in favour of loading straight into the environment, which is exactly what script files can't do
+#[allow(deprecated)]
+fn read_env_file(env_file: &str) -> Result<Option<Vec<(String, String)>>, String> {
+    let (path, optional) = parse_entry(env_file);
+    if optional && !Path::new(path).exists() {
+        return Ok(None);
+    }
+    let err = format!("Requested environment variable file '{}' could not be loaded. Either the file doesn't exist, Bonnie doesn't have the permissions necessary to access it, or something inside it can't be processed.", path);
+    let iter = match dotenv::from_filename_iter(path) {
+        Ok(iter) => iter,
+        Err(_) => return Err(err),
+    };
+    let mut vars = Vec::new();
+    for var in iter {
+        match var {
+            Ok(var) => vars.push(var),
+            Err(_) => return Err(err),
+        }
+    }
+
+    Ok(Some(vars))
+}
@@ -2,11 +2,15 @@
 
 use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
 
 // This can be changed by the user with the `BONNIE_CONF` environment variable
 pub const DEFAULT_BONNIE_CFG_PATH: &str = "./bonnie.toml";
+// The file names a config can have if `BONNIE_CONF` isn't set, tried in order (the first is the default)
+const BONNIE_CFG_FILE_NAMES: [&str; 4] =
+    ["bonnie.toml", "bonnie.yaml", "bonnie.yml", "bonnie.json"];
 
-// Extracts the config from the TOML file at the given path
+// Extracts the config from the file at the configured path (in whatever format it's in)
 pub fn get_cfg() -> Result<String, String> {
     // Get the path of the config
     let path = get_cfg_path()?;
@@ -18,13 +22,26 @@ pub fn get_cfg() -> Result<String, String> {
 }
 
 // Gets the path to the config file based on given environment variables
+// If none is set, this will use the first default path that exists (`bonnie.toml`, then `bonnie.yaml`, `bonnie.yml`, and `bonnie.json`)
 // This will return an error if the `BONNIE_CONF` environment variable is set, but is invalid
-fn get_cfg_path() -> Result<String, String> {
+pub fn get_cfg_path() -> Result<String, String> {
     // Get the `BONNIE_CONF` variable
     let given_path = env::var("BONNIE_CONF");
     match given_path {
         Ok(path) => Ok(path),
         Err(env::VarError::NotUnicode(_)) => Err(String::from("The path to your Bonnie configuration file given in the 'BONNIE_CONF' environment variable contained invalid characters. Please make sure it only contains valid Unicode.")),
-        Err(env::VarError::NotPresent) => Ok(DEFAULT_BONNIE_CFG_PATH.to_string()) // If the env var wasn't found, then use the default
+        // If the env var wasn't found, then use the default (falling back to it if nothing exists so it's created by `bonnie -i`)
+        Err(env::VarError::NotPresent) => Ok(find_cfg_in_dir(Path::new("."))
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|| DEFAULT_BONNIE_CFG_PATH.to_string())),
     }
 }
+
+// Finds the config in the given directory, trying each of the file names a config can have in order (see `get_cfg_path`)
+// This is also used to find the configs of workspace members
+pub fn find_cfg_in_dir(dir: &Path) -> Option<PathBuf> {
+    BONNIE_CFG_FILE_NAMES
+        .iter()
+        .map(|file_name| dir.join(file_name))
+        .find(|path| fs::metadata(path).is_ok())
+}
@@ -0,0 +1,278 @@
+// This file contains the logic to export ordered subcommands as flowcharts (in Graphviz DOT or Mermaid syntax)
+// This works from the parsed form of Bones directives, so the diagrams always reflect what Bonnie will actually run
+
+use crate::bones::BonesDirective;
+use crate::schema;
+
+// The formats a graph can be exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+impl GraphFormat {
+    // Gets the format requested by a command-line flag, if it is one
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "--dot" => Some(GraphFormat::Dot),
+            "--mermaid" => Some(GraphFormat::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+// A single run of a subcommand in a directive (the same subcommand may be run in several places, and each gets its own node)
+struct Node {
+    id: String,
+    name: String,
+    desc: Option<String>,
+    // If the subcommand has ordered subcommands of its own, they're graphed inside it
+    inner: Option<Graph>,
+}
+// A progression from one subcommand to another, labelled with the operator that triggers it
+struct Edge {
+    from: String,
+    to: String,
+    label: String,
+}
+struct Graph {
+    // The ID of the node that's run first
+    start: String,
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    // The ID and label of the node for the cleanup steps (`finally`), which are run after every branch (if there are any)
+    cleanup: Option<(String, String)>,
+}
+impl Graph {
+    // Builds a graph from the given directive, which runs the given subcommands (or other scripts in the given config), and then the given cleanup steps
+    // The counter is used to give every node a unique ID across nested graphs
+    fn build(
+        directive: &BonesDirective,
+        subcommands: &schema::Scripts,
+        finally: &[String],
+        cfg: &schema::Config,
+        counter: &mut usize,
+    ) -> Result<Self, String> {
+        let mut graph = Graph {
+            start: String::new(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            cleanup: None,
+        };
+        graph.start = graph.add_directive(directive, subcommands, cfg, counter)?;
+        if !finally.is_empty() {
+            graph.cleanup = Some((format!("n{}", counter), finally.join(", ")));
+            *counter += 1;
+        }
+
+        Ok(graph)
+    }
+    // Gets the IDs of the nodes every branch ends at (those that don't lead to anything else), which are followed by the cleanup steps
+    fn ends(&self) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|node| !self.edges.iter().any(|edge| edge.from == node.id))
+            .map(|node| node.id.as_str())
+            .collect()
+    }
+    // Adds the nodes and edges for the given directive to this graph recursively, returning the ID of the node it starts at
+    fn add_directive(
+        &mut self,
+        directive: &BonesDirective,
+        subcommands: &schema::Scripts,
+        cfg: &schema::Config,
+        counter: &mut usize,
+    ) -> Result<String, String> {
+        let name = directive.command_name();
+        let id = format!("n{}", counter);
+        *counter += 1;
+        let (subcommand, inner) = match subcommands.get(name) {
+            // Nested ordered subcommands get their own graph
+            Some(subcommand) => match (&subcommand.subcommands, &subcommand.order) {
+                (Some(nested_subcommands), Some(nested_directive)) => (
+                    subcommand,
+                    Some(Graph::build(
+                        nested_directive,
+                        nested_subcommands,
+                        &subcommand.finally,
+                        cfg,
+                        counter,
+                    )?),
+                ),
+                _ => (subcommand, None),
+            },
+            // References to other scripts aren't expanded (they could reference each other in cycles)
+            None => (cfg.get_script_for_path(name)?, None),
+        };
+        self.nodes.push(Node {
+            id: id.clone(),
+            name: name.to_string(),
+            desc: subcommand.description.clone(),
+            inner,
+        });
+        // Sort the conditions so the output is the same every time (they're stored in a `HashMap`)
+        let mut conditions: Vec<(String, &Option<BonesDirective>)> = directive
+            .conditions()
+            .iter()
+            .map(|(operator, next)| (operator.to_string(), next))
+            .collect();
+        conditions.sort_by(|(operator, _), (operator2, _)| operator.cmp(operator2));
+        for (operator, next) in conditions {
+            // Conditions that don't lead anywhere just end the chain, so they don't need an edge
+            if let Some(next) = next {
+                let next_id = self.add_directive(next, subcommands, cfg, counter)?;
+                self.edges.push(Edge {
+                    from: id.clone(),
+                    to: next_id,
+                    label: operator,
+                });
+            }
+        }
+
+        Ok(id)
+    }
+    // Renders this graph as the body of a DOT digraph, indented to the given depth
+    fn render_dot(&self, depth: usize) -> Vec<String> {
+        let indent = "    ".repeat(depth);
+        let mut lines = Vec::new();
+        for node in &self.nodes {
+            let mut label = escape_dot(&node.name);
+            if let Some(desc) = &node.desc {
+                label += &format!("\\n{}", escape_dot(desc));
+            }
+            lines.push(format!("{}{} [label=\"{}\"];", indent, node.id, label));
+            if let Some(inner) = &node.inner {
+                lines.push(format!("{}subgraph cluster_{} {{", indent, node.id));
+                lines.push(format!(
+                    "{}    label=\"{}\";",
+                    indent,
+                    escape_dot(&node.name)
+                ));
+                lines.extend(inner.render_dot(depth + 1));
+                lines.push(format!("{}}}", indent));
+                // Running a subcommand with ordered subcommands starts its own chain
+                lines.push(format!(
+                    "{}{} -> {} [style=dashed];",
+                    indent, node.id, inner.start
+                ));
+            }
+        }
+        for edge in &self.edges {
+            lines.push(format!(
+                "{}{} -> {} [label=\"{}\"];",
+                indent,
+                edge.from,
+                edge.to,
+                escape_dot(&edge.label)
+            ));
+        }
+        if let Some((cleanup_id, cleanup_label)) = &self.cleanup {
+            lines.push(format!(
+                "{}{} [label=\"{}\"];",
+                indent,
+                cleanup_id,
+                escape_dot(cleanup_label)
+            ));
+            for end in self.ends() {
+                lines.push(format!(
+                    "{}{} -> {} [label=\"finally\", style=dotted];",
+                    indent, end, cleanup_id
+                ));
+            }
+        }
+
+        lines
+    }
+    // Renders this graph as the body of a Mermaid flowchart, indented to the given depth
+    fn render_mermaid(&self, depth: usize) -> Vec<String> {
+        let indent = "    ".repeat(depth);
+        let mut lines = Vec::new();
+        for node in &self.nodes {
+            let mut label = escape_mermaid(&node.name);
+            if let Some(desc) = &node.desc {
+                label += &format!("<br/>{}", escape_mermaid(desc));
+            }
+            lines.push(format!("{}{}[\"{}\"]", indent, node.id, label));
+            if let Some(inner) = &node.inner {
+                lines.push(format!(
+                    "{}subgraph {}_steps [\"{}\"]",
+                    indent,
+                    node.id,
+                    escape_mermaid(&node.name)
+                ));
+                lines.extend(inner.render_mermaid(depth + 1));
+                lines.push(format!("{}end", indent));
+                // Running a subcommand with ordered subcommands starts its own chain
+                lines.push(format!("{}{} -.-> {}", indent, node.id, inner.start));
+            }
+        }
+        for edge in &self.edges {
+            lines.push(format!(
+                "{}{} -->|\"{}\"| {}",
+                indent,
+                edge.from,
+                escape_mermaid(&edge.label),
+                edge.to
+            ));
+        }
+        if let Some((cleanup_id, cleanup_label)) = &self.cleanup {
+            lines.push(format!(
+                "{}{}[\"{}\"]",
+                indent,
+                cleanup_id,
+                escape_mermaid(cleanup_label)
+            ));
+            for end in self.ends() {
+                lines.push(format!(
+                    "{}{} -.->|\"finally\"| {}",
+                    indent, end, cleanup_id
+                ));
+            }
+        }
+
+        lines
+    }
+}
+
+// Escapes a string for use inside a quoted DOT label
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+// Escapes a string for use inside a quoted Mermaid label
+fn escape_mermaid(text: &str) -> String {
+    text.replace('"', "#quot;")
+}
+
+// Graphs the ordered subcommands of the given command in the given format
+// This will return an error if the command doesn't have ordered subcommands (there's nothing to graph)
+pub fn graph_command(
+    name: &str,
+    command: &schema::Command,
+    cfg: &schema::Config,
+    format: GraphFormat,
+) -> Result<String, String> {
+    let (subcommands, directive) = match (&command.subcommands, &command.order) {
+        (Some(subcommands), Some(directive)) => (subcommands, directive),
+        _ => return Err(format!("The command '{}' doesn't have ordered subcommands, so there's nothing to graph. Only commands that specify `order` can be graphed.", name))
+    };
+    let graph = Graph::build(directive, subcommands, &command.finally, cfg, &mut 0)?;
+
+    let lines = match format {
+        GraphFormat::Dot => {
+            let mut lines = vec![
+                format!("digraph \"{}\" {{", escape_dot(name)),
+                "    node [shape=box];".to_string(),
+            ];
+            lines.extend(graph.render_dot(1));
+            lines.push("}".to_string());
+            lines
+        }
+        GraphFormat::Mermaid => {
+            let mut lines = vec!["flowchart TD".to_string()];
+            lines.extend(graph.render_mermaid(1));
+            lines
+        }
+    };
+
+    Ok(lines.join("\n"))
+}
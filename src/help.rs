@@ -12,13 +12,17 @@ This just summarizes the functionality of this command, not the syntax of Bonnie
 -h, --help                                      prints this help page
 -v, --version                                   prints the current version of Bonnie
 -i, --init [-t, --template <template-file>]     creates a new `bonnie.toml` configuration (or whatever's set in `BONNIE_CONF`), using the specified template file if provided
--c, --cache                                     caches the Bonnie configuration file to `.bonnie.cache.json` for performance (this cache must be MANUALLY updated by re-running this command!)
+-c, --cache                                     caches the Bonnie configuration file to `.bonnie.cache.json` (or `.bonnie.cache.bin` if built with the `binary-cache` feature) for performance (this cache is rebuilt automatically whenever the configuration or any file it uses changes)
+--dry-run <command-name>                        prints what would be run for the given command (including its dependencies, in order) without running anything
+--all <command-name>                            runs the given command in every member of the workspace (see `workspace.members`), printing a summary at the end
 
 help [command-name]                             prints the help page for the current Bonnie configuration or for the given command
+graph [--dot, --mermaid] <command-name>         prints a flowchart of the given command's ordered subcommands in Graphviz DOT (default) or Mermaid syntax
 
-The expected location of a Bonnie configuration file can be changed from the default `./bonnie.toml` by setting the `BONNIE_CONF` environment variable.
-The expected location of a Bonnie cache file can be changed from the default `./.bonnie.cache.json` by setting the `BONNIE_CACHE` environment variable.
+The expected location of a Bonnie configuration file can be changed from the default `./bonnie.toml` by setting the `BONNIE_CONF` environment variable. If that doesn't exist, `./bonnie.yaml`, `./bonnie.yml`, and `./bonnie.json` will be tried in that order.
+The expected location of a Bonnie cache file can be changed from the default `./.bonnie.cache.json` (or `./.bonnie.cache.bin`) by setting the `BONNIE_CACHE` environment variable.
 The expected location of your default template can be changed from the default `~/.bonnie/template.toml` by setting the `BONNIE_TEMPLATE` environment variable.
+The target scripts are selected for (e.g. `windows` or `aarch64-unknown-linux-musl`) can be overridden by setting the `BONNIE_TARGET` environment variable, which is useful with `--dry-run`.
 
 Further information can be found at https://github.com/arctic-hen7/bonnie/wiki.",
         version = BONNIE_VERSION
@@ -10,25 +10,25 @@ pub fn init(template: Option<String>, cfg_path: &str) -> Result<(), String> {
         Err(String::from("A Bonnie configuration file already exists in this directory. If you want to create a new one, please delete the old one first."))
     } else {
         // Check if a template has been given
-        let output;
-        if matches!(template, Some(_)) && fs::metadata(template.as_ref().unwrap()).is_ok() {
-            let template_path = template.unwrap();
+        let output = match template {
             // We have a valid template file
-            let contents = fs::read_to_string(&template_path);
-            let contents = match contents {
-                Ok(contents) => contents,
-                Err(_) => return Err(format!("An error occurred while attempting to read the given template file '{}'. Please make sure the file exists and you have the permissions necessary to read from it.", &template_path))
-            };
-            output = fs::write(cfg_path, contents);
-        } else if matches!(template, Some(_)) && fs::metadata(template.as_ref().unwrap()).is_err() {
+            Some(template_path) if fs::metadata(&template_path).is_ok() => {
+                let contents = fs::read_to_string(&template_path);
+                let contents = match contents {
+                    Ok(contents) => contents,
+                    Err(_) => return Err(format!("An error occurred while attempting to read the given template file '{}'. Please make sure the file exists and you have the permissions necessary to read from it.", &template_path))
+                };
+                fs::write(cfg_path, contents)
+            }
             // We have a template file that doesn't exist
-            return Err(format!("The given template file at '{}' does not exist or can't be read. Please make sure the file exists and you have the permissions necessary to read from it.", template.as_ref().unwrap()));
-        } else {
-            // Try to get the default template file from `~/.bonnie/template.toml`
-            // If it's not available, we'll use a pre-programmed default
-            let template = get_default_template()?;
-            output = fs::write(cfg_path, template);
-        }
+            Some(template_path) => return Err(format!("The given template file at '{}' does not exist or can't be read. Please make sure the file exists and you have the permissions necessary to read from it.", template_path)),
+            None => {
+                // Try to get the default template file from `~/.bonnie/template.toml`
+                // If it's not available, we'll use a pre-programmed default
+                let template = get_default_template()?;
+                fs::write(cfg_path, template)
+            }
+        };
 
         match output {
     		Ok(_) => Ok(()),
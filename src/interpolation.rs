@@ -0,0 +1,500 @@
+// This file contains the engine that interpolates arguments and environment variables into commands
+// This works in a single pass over the command, so values that are inserted are never interpolated into again, and names that are prefixes of each other (like `a` and `ab`) can't clobber each other
+// The syntax is:
+//  - `%name` inserts the value of an argument or environment variable (if several names match, the longest one is used)
+//  - `%raw:name` inserts the value without quoting it for the shell (see below)
+//  - `%{name}` does the same, but delimits the name so it can be followed directly by more text
+//  - `%{NAME:-default}` and `%{NAME:?message}` insert an environment variable with a shell-style fallback (these don't need to be listed in `env_vars`)
+//  - `%%` inserts all the arguments not consumed by named arguments (each quoted separately)
+//  - `%[name]` inserts one of Bonnie's builtins (see `Builtins`), any other `%[...]` is left alone
+//  - `%[prev.name]` and `%[prev.exit_code]` become placeholders for the details of the previous step in an ordered chain, which Bones fills in at runtime
+//  - `%[env.NAME]` inserts an environment variable (these come from `[vars]`, which leave them to be resolved here so they're never cached)
+//  - `\%` gives a literal `%` (so `\%%` gives `%%` and `\%name` gives `%name`)
+// Anything else (like a `%` that doesn't start a known name) is left as it is
+// Argument values are quoted for the shell they'll be run in (POSIX shells and PowerShell), so they're always passed as single words and can't inject anything
+// Bonnie doesn't know how to quote for any other shell (like `cmd`), so values are inserted as they are there, with a warning
+// Environment variables are trusted to be what the user wants, so they're inserted as they are
+
+use crate::bones::CommandPart;
+use crate::schema::Shell;
+use crate::target::Target;
+use crate::vars::env_ref_error;
+use crate::version::BONNIE_VERSION;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The executables of the shells we quote for as POSIX shells
+const POSIX_SHELLS: [&str; 8] = ["sh", "bash", "dash", "zsh", "ksh", "mksh", "ash", "yash"];
+
+// How argument values are quoted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quoting {
+    // For `sh`, `bash`, `zsh`, etc.
+    Posix,
+    // For `powershell` and `pwsh`
+    PowerShell,
+    // For any other shell (like `cmd`), which we don't know how to quote for, so values are inserted as they are (with a warning, see `Command::prepare`)
+    Unknown,
+}
+impl Quoting {
+    // Works out how to quote values for the given shell from its executable
+    pub fn for_shell(shell: &Shell) -> Self {
+        let executable = shell.parts.first().map_or("", |part| part.as_str());
+        // This handles full paths and `.exe` extensions on Windows
+        let executable = executable
+            .rsplit(&['/', '\\'][..])
+            .next()
+            .unwrap_or(executable)
+            .to_lowercase();
+        let executable = executable.trim_end_matches(".exe");
+        match executable {
+            "powershell" | "pwsh" => Self::PowerShell,
+            _ if POSIX_SHELLS.contains(&executable) => Self::Posix,
+            _ => Self::Unknown,
+        }
+    }
+    // Checks if the given value would be treated as a single literal word by the shell without quoting
+    pub fn is_safe(&self, value: &str) -> bool {
+        match self {
+            Self::Posix => {
+                !value.is_empty()
+                    && value
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c))
+            }
+            // Commas make arrays, and a leading `@` or `-` would be splatting or a parameter (backticks are escapes, so they're never safe)
+            // We don't know what's special for other shells, so we hold them to the same standard (this is only used to warn about them)
+            Self::PowerShell | Self::Unknown => {
+                !value.is_empty()
+                    && !value.starts_with(&['@', '-'][..])
+                    && value
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "_@%+=:./-".contains(c))
+            }
+        }
+    }
+    // Quotes the given value so the shell will treat it as a single literal word
+    // Values that don't need quoting are left as they are to keep commands readable
+    pub fn quote(&self, value: &str) -> String {
+        if self.is_safe(value) {
+            return value.to_string();
+        }
+        match self {
+            // Nothing is special inside single quotes, so we only have to end them around literal single quotes
+            Self::Posix => format!("'{}'", value.replace('\'', "'\\''")),
+            // Single quotes are escaped by doubling them (PowerShell also treats curly single quotes as single quotes)
+            Self::PowerShell => {
+                let escaped: String = value
+                    .chars()
+                    .flat_map(|c| match c {
+                        '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' => vec![c, c],
+                        _ => vec![c],
+                    })
+                    .collect();
+                format!("'{}'", escaped)
+            }
+            Self::Unknown => value.to_string(),
+        }
+    }
+}
+
+// The values Bonnie supplies itself, which can be interpolated as `%[name]`
+pub struct Builtins {
+    // The OS we're running on (e.g. `linux`)
+    os: String,
+    // The CPU architecture (e.g. `x86_64`)
+    arch: String,
+    // The directory Bonnie was run in
+    cwd: String,
+    // The directory containing the config file
+    config_dir: String,
+    // The path of the script being run (e.g. `db.migrate`)
+    script: String,
+    // The number of seconds since the Unix epoch when the command was prepared (so it's the same for every stage)
+    timestamp: String,
+    bonnie_version: String,
+}
+impl Builtins {
+    // Gets the builtins for the script at the given path in the config in the given directory
+    pub fn new(script: &str, config_dir: &str) -> Self {
+        let target = Target::current();
+        Self {
+            os: target.os,
+            arch: target.arch,
+            // If we can't get the current directory, it's the one we're in
+            cwd: env::current_dir()
+                .map(|cwd| cwd.to_string_lossy().to_string())
+                .unwrap_or_else(|_| String::from(".")),
+            config_dir: config_dir.to_string(),
+            script: script.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs())
+                .to_string(),
+            bonnie_version: BONNIE_VERSION.to_string(),
+        }
+    }
+    // Gets the builtin with the given name
+    fn get(&self, name: &str) -> Option<&str> {
+        Some(match name {
+            "os" => &self.os,
+            "arch" => &self.arch,
+            "cwd" => &self.cwd,
+            "config_dir" => &self.config_dir,
+            "script" => &self.script,
+            "timestamp" => &self.timestamp,
+            "bonnie_version" => &self.bonnie_version,
+            _ => return None,
+        })
+    }
+}
+
+// The environment variables a script can use
+pub struct ScriptEnv<'a> {
+    pub env_vars: &'a [String],
+    // These can be interpolated just like normal ones, they're only different when the command is printed (see `Values::secrets`)
+    pub secret_env_vars: &'a [String],
+    // The variables from the script's own environment variable files, which take precedence over those in Bonnie's environment (see `env_files.rs`)
+    pub from_files: HashMap<String, String>,
+}
+impl ScriptEnv<'_> {
+    // Gets the value of the environment variable with the given name
+    fn get(&self, name: &str) -> Option<String> {
+        match self.from_files.get(name) {
+            Some(value) => Some(value.to_string()),
+            None => env::var(name).ok(),
+        }
+    }
+}
+
+// A value that can be interpolated by name
+struct Named {
+    name: String,
+    // The value as it should be inserted normally (quoted if it's an argument)
+    value: String,
+    // The value as it was given, for `%raw:name`
+    raw: String,
+    // Whether or not this should be masked when the command is printed
+    secret: bool,
+}
+
+// The values that can be interpolated into a single command
+pub struct Values<'a> {
+    // The named values (arguments and listed environment variables), with environment variables first so they take precedence as they always have
+    named: Vec<Named>,
+    // The arguments left over after the named ones (already quoted), which are inserted with `%%`
+    remaining_args: Vec<String>,
+    builtins: Builtins,
+    quoting: Quoting,
+    // Whether or not `%%` has been interpolated with these values yet
+    used_remaining_args: Cell<bool>,
+    // Whether or not any of the arguments needed quoting that we couldn't do (because we don't know how to quote for the shell)
+    has_unquoted_args: bool,
+    env: ScriptEnv<'a>,
+}
+impl<'a> Values<'a> {
+    // Creates the values for a command with the given arguments and environment variables, taking the values of the arguments from those given to the program
+    // Argument values will be quoted in the given way
+    // This will return an error if too few arguments were given or if an environment variable couldn't be loaded
+    pub fn new(
+        name: &str,
+        args: &[String],
+        env: ScriptEnv<'a>,
+        prog_args: &[String],
+        builtins: Builtins,
+        quoting: Quoting,
+    ) -> Result<Self, String> {
+        // Check if the correct number of arguments was provided
+        // Even if we're inserting the rest later, we still need the mandatory ones
+        if args.len() > prog_args.len() {
+            return Err(
+                format!(
+                    "The command '{command}' requires {num_required_args} argument(s), but {num_given_args} argument(s) were provided (too few). Please provide all the required arguments.",
+                    command=name,
+                    num_required_args=args.len(),
+                    num_given_args=&prog_args.len()
+                )
+            );
+        }
+        let mut named = Vec::new();
+        let all_env_vars = env
+            .env_vars
+            .iter()
+            .map(|name| (name, false))
+            .chain(env.secret_env_vars.iter().map(|name| (name, true)));
+        for (env_var_name, secret) in all_env_vars {
+            let env_var = match env.get(env_var_name) {
+                Some(env_var) => env_var,
+                None => return Err(format!("The environment variable '{}' couldn't be loaded. This means it either hasn't been defined (you may need to load another environment variable file) or contains invalid characters.", env_var_name))
+            };
+            named.push(Named {
+                name: env_var_name.to_string(),
+                value: env_var.to_string(),
+                raw: env_var,
+                secret,
+            });
+        }
+        // The arrays are the same length, see above check
+        for (arg, value) in args.iter().zip(prog_args.iter()) {
+            named.push(Named {
+                name: arg.to_string(),
+                value: quoting.quote(value),
+                raw: value.to_string(),
+                secret: false,
+            });
+        }
+        let (_, remaining_args) = prog_args.split_at(args.len());
+
+        Ok(Self {
+            named,
+            remaining_args: remaining_args
+                .iter()
+                .map(|arg| quoting.quote(arg))
+                .collect(),
+            builtins,
+            quoting,
+            used_remaining_args: Cell::new(false),
+            has_unquoted_args: quoting == Quoting::Unknown
+                && prog_args.iter().any(|arg| !quoting.is_safe(arg)),
+            env,
+        })
+    }
+    // Checks if the remaining arguments have been inserted into anything these values have been interpolated into
+    pub fn used_remaining_args(&self) -> bool {
+        self.used_remaining_args.get()
+    }
+    // Checks if any of the arguments would be inserted without the quoting they need
+    pub fn has_unquoted_args(&self) -> bool {
+        self.has_unquoted_args
+    }
+    // Gets the variables from the script's own environment variable files, consuming `self` (these need to be set for the script's process)
+    pub fn into_env_from_files(self) -> HashMap<String, String> {
+        self.env.from_files
+    }
+    // Gets the values of all the secret environment variables, which should be masked wherever the command is printed
+    // Empty values are left out, since there's nothing to mask
+    pub fn secrets(&self) -> Vec<String> {
+        self.named
+            .iter()
+            .filter(|named| named.secret && !named.raw.is_empty())
+            .map(|named| named.raw.to_string())
+            .collect()
+    }
+    // Gets the value of the environment variable with the given name, as the script will see it
+    pub fn get_env(&self, name: &str) -> Option<String> {
+        self.env.get(name)
+    }
+    // Gets the value with exactly the given name
+    fn get(&self, name: &str) -> Option<&Named> {
+        self.named.iter().find(|named| named.name == name)
+    }
+    // Gets the value with the longest name that the given text starts with
+    fn longest_match(&self, text: &str) -> Option<&Named> {
+        self.named
+            .iter()
+            .filter(|named| !named.name.is_empty() && text.starts_with(named.name.as_str()))
+            // `max_by_key` keeps the last maximum, so we reverse to let earlier names win ties
+            .rev()
+            .max_by_key(|named| named.name.len())
+    }
+}
+
+// Interpolates the given values into the given command string, giving the parts of the command (see `CommandPart`)
+// This returns an error if a required environment variable (`%{NAME:?message}`) isn't set
+pub fn interpolate(cmd_str: &str, values: &Values) -> Result<Vec<CommandPart>, String> {
+    let mut parts = Vec::new();
+    // The text since the last placeholder
+    let mut interpolated = String::new();
+    let mut rest = cmd_str;
+    while let Some(idx) = rest.find(&['%', '\\'][..]) {
+        interpolated.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+
+        if let Some(after) = rest.strip_prefix("\\%%") {
+            interpolated.push_str("%%");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("\\%") {
+            interpolated.push('%');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('\\') {
+            // A backslash that isn't escaping anything of ours is the shell's business
+            interpolated.push('\\');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("%%") {
+            interpolated.push_str(&values.remaining_args.join(" "));
+            values.used_remaining_args.set(true);
+            rest = after;
+        } else if let Some((placeholder, after)) = strip_prev_placeholder(rest) {
+            parts.push(CommandPart::Text(std::mem::take(&mut interpolated)));
+            parts.push(placeholder);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("%[") {
+            let bracketed = after
+                .find(']')
+                .and_then(|end| Some((interpolate_bracketed(&after[..end], values)?, end)));
+            if let Some((value, end)) = bracketed {
+                interpolated.push_str(&value?);
+                rest = &after[end + 1..];
+            } else {
+                // Anything else is handled elsewhere, so we pass the whole thing through untouched (so nothing inside is interpolated either)
+                let len = after.find(']').map_or(after.len(), |idx| idx + 1);
+                interpolated.push_str(&rest[..len + 2]);
+                rest = &after[len..];
+            }
+        } else if let Some(after) = rest.strip_prefix("%{") {
+            let delimited = after
+                .find('}')
+                .and_then(|end| Some((interpolate_delimited(&after[..end], values)?, end)));
+            match delimited {
+                Some((value, end)) => {
+                    interpolated.push_str(&value?);
+                    rest = &after[end + 1..];
+                }
+                // This isn't something we know (it could be PowerShell's `%{ ... }`), so it's left alone
+                None => {
+                    interpolated.push('%');
+                    rest = &rest[1..];
+                }
+            }
+        } else if let Some(named) = rest
+            .strip_prefix("%raw:")
+            .and_then(|after| values.longest_match(after))
+        {
+            interpolated.push_str(&named.raw);
+            rest = &rest["%raw:".len() + named.name.len()..];
+        } else {
+            let after = &rest[1..];
+            match values.longest_match(after) {
+                Some(named) => {
+                    interpolated.push_str(&named.value);
+                    rest = &after[named.name.len()..];
+                }
+                None => {
+                    interpolated.push('%');
+                    rest = after;
+                }
+            }
+        }
+    }
+    interpolated.push_str(rest);
+    parts.push(CommandPart::Text(interpolated));
+
+    Ok(parts)
+}
+
+// Gets the placeholder for the details of the previous step that the given text starts with (if there is one), along with the text after it
+fn strip_prev_placeholder(text: &str) -> Option<(CommandPart, &str)> {
+    if let Some(after) = text.strip_prefix("%[prev.name]") {
+        Some((CommandPart::PrevName, after))
+    } else {
+        text.strip_prefix("%[prev.exit_code]")
+            .map(|after| (CommandPart::PrevExitCode, after))
+    }
+}
+
+// Interpolates the contents of a bracketed `%[...]` block, which is either a builtin or an environment variable
+// This returns `None` if the contents are interpolated elsewhere, in which case the block should be left as it is
+fn interpolate_bracketed(contents: &str, values: &Values) -> Option<Result<String, String>> {
+    if let Some(env_var_name) = contents.strip_prefix("env.") {
+        return Some(
+            values
+                .get_env(env_var_name)
+                .ok_or_else(|| env_ref_error(env_var_name)),
+        );
+    }
+    // Paths can have spaces in them, so builtins are quoted like arguments
+    let builtin = values.builtins.get(contents)?;
+    Some(Ok(values.quoting.quote(builtin)))
+}
+
+// Interpolates the contents of a delimited `%{...}` block
+// This returns `None` if the contents aren't a known name or a valid fallback, in which case the block should be left as it is
+fn interpolate_delimited(contents: &str, values: &Values) -> Option<Result<String, String>> {
+    if let Some(named) = values.get(contents) {
+        return Some(Ok(named.value.to_string()));
+    }
+    if let Some(named) = contents
+        .strip_prefix("raw:")
+        .and_then(|name| values.get(name))
+    {
+        return Some(Ok(named.raw.to_string()));
+    }
+    // Fallbacks look like `NAME:-default` or `NAME:?message`
+    let colon_idx = contents.find(':')?;
+    let (env_var_name, operator_and_fallback) = contents.split_at(colon_idx);
+    if !is_env_var_name(env_var_name) {
+        return None;
+    }
+    let (operator, fallback) = match &operator_and_fallback[1..] {
+        fallback if fallback.starts_with('-') => ("-", &fallback[1..]),
+        fallback if fallback.starts_with('?') => ("?", &fallback[1..]),
+        _ => return None,
+    };
+
+    Some(match values.env.get(env_var_name) {
+        // Like in shells, empty variables are treated as unset
+        Some(env_var) if !env_var.is_empty() => Ok(env_var),
+        _ if operator == "-" => Ok(fallback.to_string()),
+        _ => Err(match fallback {
+            "" => format!("The environment variable '{}' must be set, but it couldn't be loaded or was empty.", env_var_name),
+            message => format!("The environment variable '{}' couldn't be loaded or was empty: {}", env_var_name, message),
+        }),
+    })
+}
+
+// Checks if the given text is a valid environment variable name for a fallback
+fn is_env_var_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+// Tests for quoting (the full engine is tested end-to-end)
+#[test]
+fn detects_powershell_from_executable() {
+    let shell = |executable: &str| Shell {
+        parts: vec![executable.to_string(), "-c".to_string()],
+        delimiter: " ; ".to_string(),
+    };
+
+    assert_eq!(
+        Quoting::for_shell(&shell("powershell")),
+        Quoting::PowerShell
+    );
+    assert_eq!(
+        Quoting::for_shell(&shell("C:\\Program Files\\PowerShell\\7\\pwsh.exe")),
+        Quoting::PowerShell
+    );
+    assert_eq!(Quoting::for_shell(&shell("/bin/bash")), Quoting::Posix);
+    assert_eq!(Quoting::for_shell(&shell("cmd")), Quoting::Unknown);
+}
+#[test]
+fn quotes_for_posix() {
+    assert_eq!(Quoting::Posix.quote("file.txt"), "file.txt");
+    assert_eq!(Quoting::Posix.quote(""), "''");
+    assert_eq!(Quoting::Posix.quote("it's $(here)"), "'it'\\''s $(here)'");
+}
+#[test]
+fn quotes_for_powershell() {
+    assert_eq!(Quoting::PowerShell.quote("file.txt"), "file.txt");
+    assert_eq!(Quoting::PowerShell.quote("it's $home"), "'it''s $home'");
+    assert_eq!(
+        Quoting::PowerShell.quote("it\u{2019}s"),
+        "'it\u{2019}\u{2019}s'"
+    );
+    assert_eq!(Quoting::PowerShell.quote("a,b"), "'a,b'");
+    assert_eq!(Quoting::PowerShell.quote("@args"), "'@args'");
+    assert_eq!(Quoting::PowerShell.quote("-Force"), "'-Force'");
+    assert_eq!(Quoting::PowerShell.quote("user@host"), "user@host");
+    assert_eq!(Quoting::PowerShell.quote("a`b"), "'a`b'");
+}
+#[test]
+fn leaves_values_for_unknown_shells() {
+    assert_eq!(Quoting::Unknown.quote("it's \"here\""), "it's \"here\"");
+}
@@ -1,18 +1,31 @@
 mod bones;
 mod cache;
 mod default_shells;
+mod diagnostics;
+mod env_files;
 mod get_cfg;
+mod graph;
 mod help;
 mod init;
+mod interpolation;
 mod raw_schema;
 mod schema;
+mod target;
 mod template;
+mod validate;
+mod vars;
 mod version;
+mod workspace;
 
-pub use crate::cache::{cache, cache_exists, load_from_cache};
-pub use crate::get_cfg::get_cfg;
+pub use crate::bones::handle_interrupts;
+pub use crate::cache::{
+    cache, cache_exists, load_from_cache, write_cache, write_cache_as, CacheFormat,
+};
+pub use crate::get_cfg::{get_cfg, get_cfg_path};
+pub use crate::graph::GraphFormat;
 pub use crate::help::help;
 pub use crate::init::init;
-pub use crate::raw_schema::Config;
+pub use crate::raw_schema::{Config, ConfigFormat};
 pub use crate::schema::Config as FinalConfig;
 pub use crate::version::BONNIE_VERSION;
+pub use crate::workspace::run_workspace;
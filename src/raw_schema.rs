@@ -5,30 +5,185 @@
 
 use crate::bones::parse_directive_str;
 use crate::default_shells::get_default_shells;
+use crate::diagnostics::SourceLocator;
+use crate::env_files::{load_global_env_files, rebase_env_file};
 use crate::schema;
+use crate::validate::{find_unknown_keys, find_unknown_keys_in_included};
+use crate::vars::{interpolate_vars_into_scripts, resolve_vars};
 use crate::version::{get_version_parts, VersionCompatibility, VersionDifference, BONNIE_VERSION};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     version: String,                // This will be used to confirm compatibility
     env_files: Option<Vec<String>>, // Files specified here have their environment variables loaded into Bonnie
+    include: Option<Vec<String>>, // Other configuration files (or glob patterns for them) whose scripts are merged into this one
     default_shell: Option<DefaultShell>,
+    workspace: Option<Workspace>, // Other projects that scripts can be run across with `bonnie --all`
+    vars: Option<HashMap<String, String>>, // Values that can be interpolated into any script as `%[vars.name]`
+    strict: Option<bool>, // If this is set, unknown keys are errors rather than warnings
+    surplus_args: Option<schema::SurplusArgs>, // What to do when a script is given arguments it doesn't use (errors by default)
+    #[serde(skip)]
+    unknown_keys: Vec<String>, // Keys found in the config that aren't part of the schema (these are only reported once we have an output for warnings)
+    #[serde(skip)]
+    source: Option<(String, ConfigFormat)>, // The source this was parsed from, so errors can point at the right place in it
+    #[serde(skip)]
+    cfg_path: Option<String>, // The path this was read from, if we know it
+    #[serde(skip)]
+    included_paths: Vec<String>, // The paths of every file that was included (so the cache can tell when they change)
+    #[serde(skip)]
+    include_patterns: Vec<String>, // The glob patterns files were included with (so the cache can tell when files are added or removed)
+    #[serde(skip)]
+    included_unknown_keys: Vec<String>, // Like `unknown_keys`, but for included files (these name the file they're in)
+    #[serde(skip)]
+    included_sources: HashMap<String, (String, ConfigFormat)>, // The sources of included files by their paths, so errors in their scripts can point at the right place
+    #[serde(skip)]
+    script_sources: HashMap<String, String>, // The path of the file every top-level script came from
     scripts: Scripts,
 }
 impl Config {
+    // Parses a config in whatever format it appears to be in (see `ConfigFormat::sniff`)
     pub fn new(cfg_string: &str) -> Result<Self, String> {
-        let cfg: Result<Self, toml::de::Error> = toml::from_str(cfg_string);
-        let cfg = match cfg {
+        Self::new_with_format(cfg_string, ConfigFormat::sniff(cfg_string))
+    }
+    // Parses a config in the given format
+    pub fn new_with_format(cfg_string: &str, format: ConfigFormat) -> Result<Self, String> {
+        // We look for unknown keys on a generic value first, since typos can make the real deserialization fail unhelpfully
+        let unknown_keys = match format.deserialize::<serde_json::Value>(cfg_string) {
+            Ok(value) => find_unknown_keys(&value),
+            // The real deserialization will give a better error for syntax problems
+            Err(_) => Vec::new(),
+        };
+        let cfg: Result<Self, String> = format.deserialize(cfg_string);
+        let mut cfg = match cfg {
             Ok(cfg) => cfg,
             // We explicitly handle the missing version for better backward-compatibility before 0.2.0 and because it's an easy mistake to make
-            Err(err) if err.to_string().starts_with("missing field `version`") => return Err("Your Bonnie configuration file appears to be missing a 'version' key. From Bonnie 0.2.0 onwards, this key is mandatory for compatibility reasons. Please add `version = \"".to_string() + BONNIE_VERSION + "\"` to the top of your Bonnie configuration file."),
-            Err(err) => return Err(format!("Invalid Bonnie configuration file. Error: '{}'", err))
+            Err(err) if err.contains("missing field `version`") => return Err(format!("Your Bonnie configuration file appears to be missing a 'version' key. From Bonnie 0.2.0 onwards, this key is mandatory for compatibility reasons. Please add {} to the top of your Bonnie configuration file.", format.version_example())),
+            Err(err) if !unknown_keys.is_empty() => return Err(format!("Invalid Bonnie configuration file ({}), probably because of these unknown keys:\n    {}\nError: '{}'", format, unknown_keys.join("\n    "), err)),
+            Err(err) => return Err(format!("Invalid Bonnie configuration file ({}). Error: '{}'", format, err))
         };
+        cfg.unknown_keys = unknown_keys;
+        cfg.source = Some((cfg_string.to_string(), format));
 
         Ok(cfg)
     }
+    // Merges the scripts and environment variable files of any included configuration files into this one, consuming `self`
+    // This takes the path this config was read from, which included paths are resolved relative to
+    // Included files can include further files, and any script defined in more than one file will cause an error
+    pub fn load_includes(mut self, cfg_path: &str) -> Result<Self, String> {
+        self.cfg_path = Some(cfg_path.to_string());
+        let includes = match self.include.take() {
+            Some(includes) => includes,
+            None => return Ok(self),
+        };
+        let mut merged = MergedIncludes {
+            sources: self
+                .scripts
+                .keys()
+                .map(|script_name| (script_name.to_string(), cfg_path.to_string()))
+                .collect(),
+            env_files: self.env_files.take().unwrap_or_default(),
+            visited: vec![canonicalize(Path::new(cfg_path))?],
+            paths: Vec::new(),
+            patterns: Vec::new(),
+            unknown_keys: Vec::new(),
+            file_sources: HashMap::new(),
+        };
+        Self::merge_includes(
+            &includes,
+            Path::new(cfg_path),
+            &mut self.scripts,
+            &mut merged,
+        )?;
+        self.env_files = Some(merged.env_files);
+        self.included_paths = merged.paths;
+        self.include_patterns = merged.patterns;
+        self.included_unknown_keys = merged.unknown_keys;
+        self.included_sources = merged.file_sources;
+        self.script_sources = merged.sources;
+
+        Ok(self)
+    }
+    // Merges the files matching the given include patterns (relative to the file that included them) into the given scripts recursively
+    fn merge_includes(
+        includes: &[String],
+        includer_path: &Path,
+        scripts: &mut Scripts,
+        merged: &mut MergedIncludes,
+    ) -> Result<(), String> {
+        let includer_dir = includer_path.parent().unwrap_or_else(|| Path::new(""));
+        for include in includes.iter() {
+            let pattern = includer_dir.join(include).to_string_lossy().to_string();
+            merged.patterns.push(pattern.to_string());
+            let paths = match glob::glob(&pattern) {
+                Ok(paths) => paths,
+                Err(err) => return Err(format!("Error in parsing Bonnie configuration file: the included path '{}' in '{}' is not a valid glob pattern ({}).", include, includer_path.display(), err))
+            };
+            let mut paths_found = false;
+            for path in paths {
+                let path = match path {
+                    Ok(path) => path,
+                    Err(err) => return Err(format!("The following error occurred while searching for files to include from '{}': '{}'.", includer_path.display(), err))
+                };
+                paths_found = true;
+                let canonical_path = canonicalize(&path)?;
+                if merged.visited.contains(&canonical_path) {
+                    continue;
+                }
+                merged.visited.push(canonical_path.clone());
+
+                let included = IncludedConfig::new(&path)?;
+                let path_str = path.to_string_lossy().to_string();
+                merged.paths.push(path_str.to_string());
+                merged.unknown_keys.extend(included.unknown_keys);
+                if let Some(source) = included.source {
+                    merged.file_sources.insert(path_str.to_string(), source);
+                }
+                for (script_name, mut command) in included.scripts.unwrap_or_default() {
+                    // Scripts' own environment variable files (and the files their conditions check for) are relative to the file they're defined in too
+                    command.rebase_paths(
+                        path.parent().unwrap_or_else(|| Path::new("")),
+                        canonical_path.parent().unwrap_or_else(|| Path::new("")),
+                    );
+                    if let Some(source) = merged.sources.get(&script_name) {
+                        return Err(format!("Error in parsing Bonnie configuration file: the script '{}' is defined in both '{}' and '{}'. Scripts from included files can't override each other, so please rename one of them.", script_name, source, path_str));
+                    }
+                    merged
+                        .sources
+                        .insert(script_name.to_string(), path_str.to_string());
+                    scripts.insert(script_name, command);
+                }
+                // Environment variable files are relative to the file that specified them
+                let included_dir = path.parent().unwrap_or_else(|| Path::new(""));
+                for env_file in included.env_files.unwrap_or_default() {
+                    merged
+                        .env_files
+                        .push(rebase_env_file(included_dir, &env_file));
+                }
+                if let Some(nested_includes) = included.include {
+                    Self::merge_includes(&nested_includes, &path, scripts, merged)?;
+                }
+            }
+            // A glob matching nothing is fine, but a missing file that was named exactly is almost certainly a mistake
+            if !paths_found && !include.contains(&['*', '?', '['][..]) {
+                return Err(format!("Error in parsing Bonnie configuration file: the file '{}' included from '{}' doesn't exist.", pattern, includer_path.display()));
+            }
+        }
+
+        Ok(())
+    }
+    // Checks whether or not a top-level script with the given name (or alias) is defined in this config and can be run directly (i.e. isn't private)
+    // This doesn't parse anything, so it can be used on configs that are never run directly (e.g. workspace members)
+    pub fn has_script(&self, script_name: &str) -> bool {
+        self.scripts.iter().any(|(name, command)| {
+            (name == script_name || command.aliases().iter().any(|alias| alias == script_name))
+                && !command.is_private(name)
+        })
+    }
     // Runs all the necessary methods to fully parse the config, consuming `self`
     // Takes the current version of Bonnie (extracted for testing purposes)
     // This accepts an output for warnings (extracted for testing)
@@ -39,12 +194,42 @@ impl Config {
     ) -> Result<schema::Config, String> {
         // These two are run for their side-effects (both also used in loading from a cache)
         Self::parse_version_against_current(&self.version, bonnie_version_str, output)?;
+        self.report_unknown_keys(output)?;
         Self::load_env_files(self.env_files.clone())?;
         // And then we get the final config
         let cfg = self.parse()?;
 
         Ok(cfg)
     }
+    // Reports any unknown keys found in the config (and any files it included), as warnings by default or as an error if `strict` is set
+    fn report_unknown_keys(&self, output: &mut impl std::io::Write) -> Result<(), String> {
+        if self.unknown_keys.is_empty() && self.included_unknown_keys.is_empty() {
+            return Ok(());
+        }
+        if self.strict.unwrap_or(false) {
+            let all_unknown_keys: Vec<String> = self
+                .unknown_keys
+                .iter()
+                .chain(self.included_unknown_keys.iter())
+                .cloned()
+                .collect();
+            return Err(format!("Error in parsing Bonnie configuration file: found the following unknown keys (`strict` is set, so these aren't allowed):\n    {}", all_unknown_keys.join("\n    ")));
+        }
+        for unknown_key in self.unknown_keys.iter() {
+            writeln!(output, "Warning: {} in your Bonnie configuration file. It will be ignored (set `strict = true` to make this an error).", unknown_key).expect("Failed to write warning.");
+        }
+        // These already name the file they're in
+        for unknown_key in self.included_unknown_keys.iter() {
+            writeln!(
+                output,
+                "Warning: {}. It will be ignored (set `strict = true` to make this an error).",
+                unknown_key
+            )
+            .expect("Failed to write warning.");
+        }
+
+        Ok(())
+    }
     // Parses the version of the config to check for compatibility issues, consuming `self`
     // We extract the version of Bonnie itself for testing purposes
     // This si generic because it's used in caching logic as well
@@ -85,21 +270,9 @@ impl Config {
     // Loads the environment variable files requested in the config
     // This is generic because it's called in caching as well
     pub fn load_env_files(env_files: Option<Vec<String>>) -> Result<(), String> {
-        let env_files = match env_files {
-            Some(env_files) => env_files,
-            None => Vec::new(),
-        };
         // Parse each of the requested environment variable files
-        for env_file in env_files.iter() {
-            // Load the file
-            // This will be loaded for the Bonnie program, which allows us to interpolate them into commands
-            let res = dotenv::from_filename(&env_file);
-            if res.is_err() {
-                return Err(format!("Requested environment variable file '{}' could not be loaded. Either the file doesn't exist, Bonnie doesn't have the permissions necessary to access it, or something inside it can't be processed.", &env_file));
-            }
-        }
-
-        Ok(())
+        // These will be loaded for the Bonnie program, which allows us to interpolate them into commands
+        load_global_env_files(&env_files.unwrap_or_default())
     }
     // Parses the rest of the config into the final form, consuming `self`
     // A very large portion of Bonnie's logic lives here or is called here (spec transformation)
@@ -133,35 +306,68 @@ impl Config {
         // We do this inside a function because it's recursive
         // Unfortunately we can't define methods on type aliases, so this goes here
         // This involves validation logic to ensure invalid property combinations aren't specified, so we need to know whether or not `order` is specified if this is parsing subcommands
+        // Errors are pointed at the right place in the source using the given path to these scripts (e.g. `["scripts", "deploy", "subcommands"]`)
+        // Scripts that came from included files are located in those files instead (with the locators given by script name)
         fn parse_scripts(
             raw_scripts: &Scripts,
             is_order_defined: bool,
+            path: &[String],
+            locator: &SourceLocator,
+            included_locators: &HashMap<&str, &SourceLocator>,
         ) -> Result<schema::Scripts, String> {
             let mut scripts: schema::Scripts = HashMap::new();
             for (script_name, raw_command) in raw_scripts.iter() {
+                let locator = included_locators
+                    .get(script_name.as_str())
+                    .copied()
+                    .unwrap_or(locator);
+                let mut script_path = path.to_vec();
+                script_path.push(script_name.to_string());
+                // Gets the path to the given key of this script
+                let key_path = |key: &str| {
+                    let mut key_path = script_path.clone();
+                    key_path.push(key.to_string());
+                    key_path
+                };
                 let command = match raw_command {
                     Command::Simple(raw_command_wrapper) => schema::Command {
                         args: Vec::new(),
                         env_vars: Vec::new(),
+                        secret_env_vars: Vec::new(),
+                        env_files: Vec::new(),
                         subcommands: None,
                         order: None,
                         cmd: Some(raw_command_wrapper.parse()), // In the simple form, a command must be given (no subcommands can be specified)
-                        description: None
+                        description: None,
+                        finally: Vec::new(),
+                        deps: Vec::new(),
+                        aliases: Vec::new(),
+                        private: script_name.starts_with('_'),
+                        when: None,
+                        else_cmd: None,
                     },
                     Command::Complex {
                         args,
                         env_vars,
+                        secret_env_vars,
+                        env_files,
                         subcommands,
                         order,
                         cmd,
-                        desc
+                        desc,
+                        finally,
+                        deps,
+                        aliases,
+                        private,
+                        when,
+                        else_cmd,
                     } => schema::Command {
                         // If `order` is defined at the level above, we can't interpolate environment variables from here (has to be done at the level `order` was specified)
                         args: match is_order_defined {
                             // Unordered subcommands can't take arguments in any case of upper-level `order` definition
-                            _ if matches!(subcommands, Some(_)) && matches!(order, None) && matches!(args, Some(_)) => return Err(format!("Error in parsing Bonnie configuration file: if `subcommands` is specified without `order`, `args` cannot be specified. This error occurred in in the '{}' script/subscript.", script_name)),
+                            _ if subcommands.is_some() && order.is_none() && args.is_some() => return Err(locator.error_at(&key_path("args"), format!("Error in parsing Bonnie configuration file: if `subcommands` is specified without `order`, `args` cannot be specified. This error occurred in in the '{}' script/subscript.", script_name))),
                             // If it was and `args` is specified, return an error
-                            true if matches!(args, Some(_)) => return Err(format!("Error in parsing Bonnie configuration file: if `order` is specified, subscripts cannot specify `args`, as no environment variables can be provided to them. Environment variables to be interpolated in ordered subcommands must be set at the top-level. This error occurred in the '{}' script/subscript.", script_name)),
+                            true if args.is_some() => return Err(locator.error_at(&key_path("args"), format!("Error in parsing Bonnie configuration file: if `order` is specified, subscripts cannot specify `args`, as no environment variables can be provided to them. Environment variables to be interpolated in ordered subcommands must be set at the top-level. This error occurred in the '{}' script/subscript.", script_name))),
                             // If it was but args` isn't specified, it doesn't matter and we just give an empty vector instead
                             true => Vec::new(),
                             // If it wasn't, no validation needed
@@ -169,52 +375,135 @@ impl Config {
                         },
                         // This doesn't need any transformation, just a simple alternative if it's `None`
                         env_vars: env_vars.as_ref().unwrap_or(&Vec::new()).to_vec(),
+                        secret_env_vars: secret_env_vars.as_ref().unwrap_or(&Vec::new()).to_vec(),
+                        env_files: env_files.as_ref().unwrap_or(&Vec::new()).to_vec(),
                         // The subcommands are parsed recursively as scripts using this very function
                         // We parse through whether or not `order` is defined (has validation implications)
                         subcommands: match subcommands {
                             // We can't use `.map()` for this because we need support for `?`
                             Some(subcommands) => Some(
-                                parse_scripts(subcommands, matches!(order, Some(_)))?
+                                parse_scripts(subcommands, order.is_some(), &key_path("subcommands"), locator, &HashMap::new())?
                             ),
                             None => None
                         },
                         // If `order` is defined at the level above and `subcommands` is defined here, `order` must be defined here too
                         order: match is_order_defined {
-                            true if matches!(subcommands, Some(_)) => match order {
+                            true if subcommands.is_some() => match order {
                                 // If it was required and was given, no problem
-                                Some(order) => Some(parse_directive_str(order)?),
+                                Some(order) => Some(parse_directive_str(order).map_err(|err| locator.error_at(&key_path("order"), err))?),
                                 // If it was required but not given, return an error
-                                None => return Err(format!("Error in parsing Bonnie configuration file: if `order` is specified, all further nested subsubcommands must also specify `order`. This occurred in the '{}' script/subscript.", script_name))
+                                None => return Err(locator.error_at(&script_path.clone(), format!("Error in parsing Bonnie configuration file: if `order` is specified, all further nested subsubcommands must also specify `order`. This occurred in the '{}' script/subscript.", script_name)))
                             }
                             // If it wasn't required, no validation needed
                             true | false => match order {
-                                Some(order) => Some(parse_directive_str(order)?),
+                                Some(order) => Some(parse_directive_str(order).map_err(|err| locator.error_at(&key_path("order"), err))?),
                                 None => None
                             }
                         },
                         // If subcommands were specified, this is optional, otherwise we return an error
                         cmd: match cmd {
                             // It was given, but there are also ordered subcommands here, so execution will be ambiguous, return an error
-                            Some(_) if matches!(order, Some(_)) => return Err(format!("Error in parsing Bonnie configuration file: both `cmd` and `order` were specified. This would lead to problems of ambiguous execution, so commands can have either the top-level `cmd` property or ordered subcommands, the two are mutually exclusive. This error occurred in in the '{}' script/subscript.", script_name)),
+                            Some(_) if order.is_some() => return Err(locator.error_at(&key_path("cmd"), format!("Error in parsing Bonnie configuration file: both `cmd` and `order` were specified. This would lead to problems of ambiguous execution, so commands can have either the top-level `cmd` property or ordered subcommands, the two are mutually exclusive. This error occurred in in the '{}' script/subscript.", script_name))),
                             // It's optional
-                            _ if matches!(subcommands, Some(_)) => cmd.as_ref().map(|cmd| cmd.parse()),
+                            _ if subcommands.is_some() => cmd.as_ref().map(|cmd| cmd.parse()),
                             // It's mandatory and given
                             Some(cmd) => Some(cmd.parse()),
                             // It's mandatory and not given
-                            None => return Err(format!("Error in parsing Bonnie configuration file: if `subcommands` is not specified, `cmd` is mandatory. This error occurred in in the '{}' script/subscript.", script_name))
+                            None => return Err(locator.error_at(&script_path.clone(), format!("Error in parsing Bonnie configuration file: if `subcommands` is not specified, `cmd` is mandatory. This error occurred in in the '{}' script/subscript.", script_name)))
+                        },
+                        description: desc.clone(),
+                        // Cleanup steps are run by the Bones engine, so they only make sense with ordered subcommands
+                        finally: match finally {
+                            Some(_) if order.is_none() => return Err(locator.error_at(&key_path("finally"), format!("Error in parsing Bonnie configuration file: `finally` can only be specified alongside `order`, as cleanup steps are run after ordered subcommands. This error occurred in the '{}' script/subscript.", script_name))),
+                            Some(finally) => finally.to_vec(),
+                            None => Vec::new()
+                        },
+                        // Ordered subcommands are only run as steps, so they never run their dependencies
+                        deps: match deps {
+                            Some(_) if is_order_defined => return Err(locator.error_at(&key_path("deps"), format!("Error in parsing Bonnie configuration file: if `order` is specified, subscripts cannot specify `deps`, as they're only run as steps of the ordered subcommands. Dependencies must be set at the top-level. This error occurred in the '{}' script/subscript.", script_name))),
+                            Some(deps) => deps.to_vec(),
+                            None => Vec::new()
+                        },
+                        // Ordered subcommands can't be invoked directly, so there's nothing to alias
+                        aliases: match aliases {
+                            Some(_) if is_order_defined => return Err(locator.error_at(&key_path("aliases"), format!("Error in parsing Bonnie configuration file: if `order` is specified, subscripts cannot specify `aliases`, as they can't be invoked directly. This error occurred in the '{}' script/subscript.", script_name))),
+                            Some(aliases) => aliases.to_vec(),
+                            None => Vec::new()
+                        },
+                        // Scripts starting with an underscore are private by convention
+                        private: private.unwrap_or(false) || script_name.starts_with('_'),
+                        when: match when {
+                            Some(when) => Some(when.parse().map_err(|err| locator.error_at(&key_path("when"), format!("Error in parsing Bonnie configuration file: {} This error occurred in the '{}' script/subscript.", err, script_name)))?),
+                            None => None
+                        },
+                        // An alternative only makes sense if there's a condition for it to be the alternative to
+                        else_cmd: match else_cmd {
+                            Some(_) if when.is_none() => return Err(locator.error_at(&key_path("else"), format!("Error in parsing Bonnie configuration file: `else` can only be specified alongside `when`, as it's what's run when the condition isn't met. This error occurred in the '{}' script/subscript.", script_name))),
+                            Some(else_cmd) => Some(else_cmd.parse()),
+                            None => None
                         },
-                        description: desc.clone()
                     },
                 };
+                // Subcommands also use their parents' environment variable files (their own come later, so they take precedence)
+                let mut command = command;
+                if let Some(subcommands) = &mut command.subcommands {
+                    prepend_env_files(subcommands, &command.env_files);
+                }
                 scripts.insert(script_name.to_string(), command);
             }
+            // Aliases can't clash with the real names of any scripts at the same level, or with each other
+            let mut alias_owners: HashMap<&str, &str> = HashMap::new();
+            for (script_name, command) in scripts.iter() {
+                let mut aliases_path = path.to_vec();
+                aliases_path.push(script_name.to_string());
+                aliases_path.push("aliases".to_string());
+                for alias in command.aliases.iter() {
+                    if scripts.contains_key(alias) {
+                        return Err(locator.error_at(&aliases_path, format!("Error in parsing Bonnie configuration file: the alias '{}' of the '{}' script/subscript clashes with the name of another script/subscript. Please rename one of them.", alias, script_name)));
+                    }
+                    if let Some(other_script_name) = alias_owners.insert(alias, script_name) {
+                        return Err(locator.error_at(&aliases_path, format!("Error in parsing Bonnie configuration file: the alias '{}' is used by both the '{}' and '{}' scripts/subscripts. Please remove it from one of them.", alias, other_script_name, script_name)));
+                    }
+                }
+            }
 
             Ok(scripts)
         }
 
-        let scripts = parse_scripts(&self.scripts, false)?;
+        let locator = match &self.source {
+            Some((src, format)) => SourceLocator::new(
+                self.cfg_path.as_deref().unwrap_or("bonnie configuration"),
+                src,
+                *format,
+            ),
+            None => SourceLocator::empty(),
+        };
+        let file_locators: HashMap<&str, SourceLocator> = self
+            .included_sources
+            .iter()
+            .map(|(path, (src, format))| (path.as_str(), SourceLocator::new(path, src, *format)))
+            .collect();
+        // Scripts from the main file won't have a locator here, so they'll use the main one
+        let included_locators: HashMap<&str, &SourceLocator> = self
+            .script_sources
+            .iter()
+            .filter_map(|(script_name, path)| {
+                Some((script_name.as_str(), file_locators.get(path.as_str())?))
+            })
+            .collect();
+        let mut scripts = parse_scripts(
+            &self.scripts,
+            false,
+            &["scripts".to_string()],
+            &locator,
+            &included_locators,
+        )?;
+        // Variables are interpolated now (after environment variable files have been loaded) so this only happens once, even when caching
+        // References to variables are still checked if there's no `[vars]` table, they just won't be found
+        let vars = resolve_vars(&self.vars.clone().unwrap_or_default())?;
+        interpolate_vars_into_scripts(&mut scripts, &vars)?;
 
-        Ok(schema::Config {
+        let cfg = schema::Config {
             default_shell,
             scripts,
             // Copy these last two in case the final config is cached and needs to be revalidated on load
@@ -223,9 +512,198 @@ impl Config {
                 None => Vec::new(),
             },
             version: self.version.clone(),
-        })
+            workspace: self.workspace.as_ref().map(|workspace| schema::Workspace {
+                members: workspace.members.to_vec(),
+                parallel: workspace.parallel.unwrap_or(false),
+            }),
+            config_dir: get_config_dir(self.cfg_path.as_deref()),
+            surplus_args: self.surplus_args.unwrap_or(schema::SurplusArgs::Error),
+            sources: self
+                .cfg_path
+                .iter()
+                .chain(self.included_paths.iter())
+                .cloned()
+                .collect(),
+            include_patterns: self.include_patterns.to_vec(),
+        };
+        // Dependencies can reference any script, so they can only be checked once everything's been parsed
+        cfg.validate_deps()?;
+
+        Ok(cfg)
+    }
+}
+// Everything gathered from included files while merging them into a config
+struct MergedIncludes {
+    sources: HashMap<String, String>, // The file every script came from, so conflicts can name both files
+    env_files: Vec<String>,
+    visited: Vec<PathBuf>, // Files that have already been merged are skipped (so overlapping globs and diamond-shaped includes are fine)
+    paths: Vec<String>,
+    patterns: Vec<String>,
+    unknown_keys: Vec<String>,
+    file_sources: HashMap<String, (String, ConfigFormat)>,
+}
+// A configuration file that's been included from another one
+// These can only define scripts and environment variable files (everything else comes from the main configuration file)
+#[derive(Debug, Clone, Deserialize)]
+struct IncludedConfig {
+    include: Option<Vec<String>>,
+    env_files: Option<Vec<String>>,
+    scripts: Option<Scripts>,
+    #[serde(skip)]
+    unknown_keys: Vec<String>, // These name the file they're in, since they're reported alongside those from other files
+    #[serde(skip)]
+    source: Option<(String, ConfigFormat)>,
+}
+impl IncludedConfig {
+    fn new(path: &Path) -> Result<Self, String> {
+        let cfg_string = match fs::read_to_string(path) {
+            Ok(cfg_string) => cfg_string,
+            Err(_) => return Err(format!("Error reading included Bonnie configuration file at '{}', make sure the file is present and you have the permissions to read it.", path.display()))
+        };
+        // Included files don't have to be in the same format as the file including them
+        let format = ConfigFormat::detect(&path.to_string_lossy(), &cfg_string);
+        // These are validated in the same way as the main configuration file, but they can have fewer keys
+        let unknown_keys: Vec<String> = match format.deserialize::<serde_json::Value>(&cfg_string) {
+            Ok(value) => find_unknown_keys_in_included(&value)
+                .into_iter()
+                .map(|unknown_key| {
+                    format!("{} in the included file '{}'", unknown_key, path.display())
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let cfg: Result<Self, String> = format.deserialize(&cfg_string);
+        match cfg {
+            Ok(cfg) => Ok(Self {
+                unknown_keys,
+                source: Some((cfg_string, format)),
+                ..cfg
+            }),
+            Err(err) if !unknown_keys.is_empty() => Err(format!(
+                "Invalid included Bonnie configuration file ({}) at '{}', probably because of these unknown keys:\n    {}\nError: '{}'",
+                format,
+                path.display(),
+                unknown_keys.join("\n    "),
+                err
+            )),
+            Err(err) => Err(format!(
+                "Invalid included Bonnie configuration file ({}) at '{}'. Error: '{}'",
+                format,
+                path.display(),
+                err
+            )),
+        }
+    }
+}
+
+// The formats Bonnie configuration files can be written in
+// They all deserialize into exactly the same schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+impl ConfigFormat {
+    // Gets the format of the config at the given path, using its extension if it has a known one and its contents otherwise
+    pub fn detect(path: &str, cfg_string: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            Some("json") => ConfigFormat::Json,
+            _ => Self::sniff(cfg_string),
+        }
+    }
+    // Guesses the format of the given config from its contents
+    // JSON must be an object, and TOML is recognized by its `key = value` pairs and `[table]` headers (anything else is assumed to be YAML)
+    pub fn sniff(cfg_string: &str) -> Self {
+        // Comments are valid in both TOML and YAML, so we skip them
+        let first_line = cfg_string
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && !line.starts_with('#'));
+        match first_line {
+            Some(line) if line.starts_with('{') => ConfigFormat::Json,
+            // YAML's flow sequences can't be at the top-level of a config (it has to be a mapping), so this must be a TOML table
+            Some(line) if line.starts_with('[') => ConfigFormat::Toml,
+            // TOML keys are followed by `=` before any `:` in their values, whereas YAML keys are followed by `:`
+            Some(line) => match (line.find('='), line.find(':')) {
+                (Some(equals_idx), Some(colon_idx)) if colon_idx < equals_idx => ConfigFormat::Yaml,
+                (Some(_), _) => ConfigFormat::Toml,
+                (None, _) => ConfigFormat::Yaml,
+            },
+            // An empty file is invalid in every format, so we'll just give TOML's error
+            None => ConfigFormat::Toml,
+        }
+    }
+    // Deserializes the given string in this format, returning the error message from the format's parser on failure
+    fn deserialize<T: DeserializeOwned>(&self, cfg_string: &str) -> Result<T, String> {
+        match self {
+            ConfigFormat::Toml => toml::from_str(cfg_string).map_err(|err| err.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(cfg_string).map_err(|err| err.to_string()),
+            ConfigFormat::Json => serde_json::from_str(cfg_string).map_err(|err| err.to_string()),
+        }
+    }
+    // Gets an example of how to write the version key in this format (for error messages)
+    fn version_example(&self) -> String {
+        match self {
+            ConfigFormat::Toml => format!("`version = \"{}\"`", BONNIE_VERSION),
+            ConfigFormat::Yaml => format!("`version: \"{}\"`", BONNIE_VERSION),
+            ConfigFormat::Json => format!("`\"version\": \"{}\",`", BONNIE_VERSION),
+        }
+    }
+}
+impl std::fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigFormat::Toml => write!(f, "TOML"),
+            ConfigFormat::Yaml => write!(f, "YAML"),
+            ConfigFormat::Json => write!(f, "JSON"),
+        }
+    }
+}
+
+// Gets the absolute path to the directory containing the config file at the given path (or the current directory if we don't know where it is, or the path as it was given if it can't be resolved)
+fn get_config_dir(cfg_path: Option<&str>) -> String {
+    let cfg_dir = cfg_path
+        .and_then(|cfg_path| Path::new(cfg_path).parent())
+        .filter(|cfg_dir| !cfg_dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    match fs::canonicalize(cfg_dir) {
+        Ok(cfg_dir) => cfg_dir.to_string_lossy().to_string(),
+        Err(_) => cfg_dir.to_string_lossy().to_string(),
     }
 }
+// Adds the given environment variable files before those of every one of the given scripts (and their subcommands)
+fn prepend_env_files(scripts: &mut schema::Scripts, env_files: &[String]) {
+    if env_files.is_empty() {
+        return;
+    }
+    for command in scripts.values_mut() {
+        let mut all_env_files = env_files.to_vec();
+        all_env_files.append(&mut command.env_files);
+        command.env_files = all_env_files;
+        if let Some(subcommands) = &mut command.subcommands {
+            prepend_env_files(subcommands, env_files);
+        }
+    }
+}
+// Gets the canonical form of the given path so the same file is recognized however it was reached
+fn canonicalize(path: &Path) -> Result<PathBuf, String> {
+    match fs::canonicalize(path) {
+        Ok(path) => Ok(path),
+        Err(err) => Err(format!("The following error occurred while resolving the path to the Bonnie configuration file at '{}': '{}'.", path.display(), err))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Workspace {
+    members: Vec<String>, // Glob patterns for the directories of member projects, relative to this config
+    parallel: Option<bool>, // Members are run one after the other by default
+}
 #[derive(Debug, Clone, Deserialize)]
 #[serde(untagged)]
 enum DefaultShell {
@@ -266,6 +744,8 @@ impl Shell {
 type TargetString = String; // A target like `linux` or `x86_64-unknown-linux-musl` (see `rustup` targets)
 type Scripts = HashMap<String, Command>;
 
+// This is only deserialized once per script, so the size of the complex variant doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Deserialize)]
 #[serde(untagged)]
 enum Command {
@@ -273,12 +753,93 @@ enum Command {
     Complex {
         args: Option<Vec<String>>,
         env_vars: Option<Vec<String>>,
-        subcommands: Option<Scripts>, // Subcommands are fully-fledged commands (mostly)
+        secret_env_vars: Option<Vec<String>>, // Like `env_vars`, but their values are masked whenever Bonnie prints a command
+        env_files: Option<Vec<String>>, // Environment variable files only loaded when this script is run (these take precedence over the global ones)
+        subcommands: Option<Scripts>,   // Subcommands are fully-fledged commands (mostly)
         order: Option<OrderString>, // If this is specified, subcomands must not specify the `args` property, it may be specified at the top-level of this script as a sibling of `order`
         cmd: Option<CommandWrapper>, // This is optional if subcommands are specified
         desc: Option<String>, // This will be rendered in the config's help page ('description' is overly verbose)
+        finally: Option<Vec<String>>, // Subcommands (or other scripts) to run after ordered subcommands no matter how they ended, only valid with `order`
+        deps: Option<Vec<String>>, // Paths to other scripts that must be run first (each only once)
+        aliases: Option<Vec<String>>, // Other names this script can be invoked with (not valid in ordered subcommands)
+        private: Option<bool>, // Private scripts can only be used as steps or dependencies of other scripts, not run directly
+        when: Option<Condition>, // A condition that must be met for this to be run (otherwise it's skipped, or `else` is run instead)
+        #[serde(rename = "else")]
+        else_cmd: Option<CommandWrapper>, // This is run instead of `cmd` (or any ordered subcommands) if `when` isn't met
     },
 }
+impl Command {
+    // Gets the other names this script can be invoked with
+    fn aliases(&self) -> &[String] {
+        match self {
+            Command::Complex {
+                aliases: Some(aliases),
+                ..
+            } => aliases,
+            _ => &[],
+        }
+    }
+    // Checks if this script (which has the given name) is private, either explicitly or by starting with an underscore
+    fn is_private(&self, script_name: &str) -> bool {
+        let private = matches!(
+            self,
+            Command::Complex {
+                private: Some(true),
+                ..
+            }
+        );
+        private || script_name.starts_with('_')
+    }
+    // Makes the paths in this script (and its subcommands) relative to the given directory, which is given as it is and canonically
+    // Environment variable files are read from where Bonnie is run, but conditions are checked from the main config's directory, so the files they check for are made absolute
+    fn rebase_paths(&mut self, dir: &Path, canonical_dir: &Path) {
+        if let Command::Complex {
+            env_files,
+            subcommands,
+            when,
+            ..
+        } = self
+        {
+            if let Some(env_files) = env_files {
+                for env_file in env_files.iter_mut() {
+                    *env_file = rebase_env_file(dir, env_file);
+                }
+            }
+            if let Some(file) = when.as_mut().and_then(|when| when.file_exists.as_mut()) {
+                *file = canonical_dir.join(&file).to_string_lossy().to_string();
+            }
+            for subcommand in subcommands
+                .iter_mut()
+                .flat_map(|subcommands| subcommands.values_mut())
+            {
+                subcommand.rebase_paths(dir, canonical_dir);
+            }
+        }
+    }
+}
+#[derive(Debug, Clone, Deserialize)]
+struct Condition {
+    env: Option<String>,
+    file_exists: Option<String>,
+    command_exists: Option<String>,
+}
+impl Condition {
+    // Parses `self` into its final form (`schema::Condition`)
+    // This will return an error if nothing was actually specified
+    fn parse(&self) -> Result<schema::Condition, String> {
+        if self.env.is_none() && self.file_exists.is_none() && self.command_exists.is_none() {
+            return Err(String::from(
+                "`when` must specify at least one of `env`, `file_exists`, or `command_exists`.",
+            ));
+        }
+
+        Ok(schema::Condition {
+            env: self.env.clone(),
+            file_exists: self.file_exists.clone(),
+            command_exists: self.command_exists.clone(),
+        })
+    }
+}
 type OrderString = String; // A string of as yet undefined syntax that defines the progression between subcommands
                            // This wraps the complexities of having different shell logic for each command in a multi-stage context
                            // subcommands are specified above this level (see `Command::Complex`)
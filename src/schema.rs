@@ -1,10 +1,29 @@
 // This file contains the final schema into which all Bonnie configurations are parsed
 // This does not reflect the actual syntax used in the configuration files themselves (see `raw_schema.rs`)
 
-use crate::bones::{Bone, BonesCommand, BonesCore, BonesDirective};
+use crate::bones::{Bone, BonesCommand, BonesCore, BonesDirective, CommandPart};
+use crate::env_files::load_script_env_files;
+use crate::graph::{graph_command, GraphFormat};
+use crate::interpolation::{interpolate, Builtins, Quoting, ScriptEnv, Values};
+use crate::target::Target;
+use crate::vars::resolve_env_refs;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
+use std::path::Path;
+
+// Gets the script with the given name or alias from the given scripts, along with its real name
+fn get_script_by_name_or_alias<'a>(
+    scripts: &'a Scripts,
+    name: &str,
+) -> Option<(&'a String, &'a Command)> {
+    match scripts.get_key_value(name) {
+        Some(script) => Some(script),
+        None => scripts
+            .iter()
+            .find(|(_, command)| command.aliases.iter().any(|alias| alias == name)),
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Config {
@@ -13,11 +32,42 @@ pub struct Config {
     // These last two properties are required for loading the config if it's cached
     pub env_files: Vec<String>,
     pub version: String,
+    pub workspace: Option<Workspace>,
+    // The absolute path to the directory containing the config file (for `%[config_dir]`)
+    pub config_dir: String,
+    pub surplus_args: SurplusArgs,
+    pub sources: Vec<String>, // The config file and any files it included (so the cache can tell when they change)
+    pub include_patterns: Vec<String>, // The glob patterns files were included with (so the cache can tell when the files they match change)
+}
+impl Config {
+    // Gets every environment variable file this config uses, both global ones and those of scripts (without `?` prefixes)
+    pub fn get_all_env_files(&self) -> Vec<String> {
+        fn add_script_env_files(scripts: &Scripts, env_files: &mut Vec<String>) {
+            for command in scripts.values() {
+                env_files.extend(command.env_files.iter().cloned());
+                if let Some(subcommands) = &command.subcommands {
+                    add_script_env_files(subcommands, env_files);
+                }
+            }
+        }
+
+        let mut env_files = self.env_files.to_vec();
+        add_script_env_files(&self.scripts, &mut env_files);
+        let mut all_env_files: Vec<String> = Vec::new();
+        for env_file in env_files {
+            let env_file = env_file.strip_prefix('?').unwrap_or(&env_file).to_string();
+            if !all_env_files.contains(&env_file) {
+                all_env_files.push(env_file);
+            }
+        }
+
+        all_env_files
+    }
 }
 impl Config {
     // Gets the command requested by the given vector of arguments
     // The given arguments are expected not to include the first program argument (`bonnie` or the like)
-    // Returns the command itself, its name, and the arguments relevant thereto
+    // Returns the command itself, its path (e.g. `db.migrate` for subcommands), and the arguments relevant thereto
     pub fn get_command_for_args(
         &self,
         args: &[String],
@@ -30,7 +80,7 @@ impl Config {
             first_time: bool,
         ) -> Result<(&'a Command, String, Vec<String>), String> {
             // Get the name of the command
-            let command_name = args.get(0);
+            let command_name = args.first();
             let command_name = match command_name {
                 Some(command_name) => command_name,
                 None => {
@@ -40,9 +90,9 @@ impl Config {
                     })
                 }
             };
-            // Try to find it among those we know
-            let command = scripts.get(command_name);
-            let command = match command {
+            // Try to find it among those we know (by its name or one of its aliases)
+            let command = get_script_by_name_or_alias(scripts, command_name);
+            let (command_name, command) = match command {
                 Some(command) => command,
                 None => {
                     return Err(match first_time {
@@ -51,10 +101,13 @@ impl Config {
                     })
                 }
             };
+            if command.private {
+                return Err(format!("The script '{}' is private, so it can't be run directly. It can still be used as a step in ordered subcommands or as a dependency of other scripts.", command_name));
+            }
             // We found it, check if it has any unordered subcommands or a root-level command
             let final_command_and_relevant_args = match &command.subcommands {
                 // It has a root-level command (which can't take arguments) and no more arguments are present, this is the command we want
-                Some(_) if matches!(command.cmd, Some(_)) && args.len() == 1 => {
+                Some(_) if command.cmd.is_some() && args.len() == 1 => {
                     (command, command_name.to_string(), {
                         // We get the arguments to the program, excluding the name of this command, these are the arguments to be inteprolated
                         let mut args_for_interpolation = args.to_vec();
@@ -63,12 +116,19 @@ impl Config {
                     })
                 }
                 // It does, recurse on them
-                Some(subcommands) if matches!(command.order, None) => {
+                Some(subcommands) if command.order.is_none() => {
                     // We remove the first argument, which is the name of this, the parent command
                     let mut args_without_this = args.to_vec();
                     args_without_this.remove(0);
-                    get_command_for_scripts_and_args(subcommands, &args_without_this, false)?
                     // It's no longer the first time obviously
+                    let (subcommand, subcommand_path, relevant_args) =
+                        get_command_for_scripts_and_args(subcommands, &args_without_this, false)?;
+                    // We give back the full path to the subcommand (e.g. `db.migrate`)
+                    (
+                        subcommand,
+                        format!("{}.{}", command_name, subcommand_path),
+                        relevant_args,
+                    )
                 }
                 // They're ordered and so individually uninvocable, this is the command we want
                 Some(_) => (command, command_name.to_string(), {
@@ -94,6 +154,112 @@ impl Config {
 
         Ok(data)
     }
+    // Gets the script at the given path, which may be a top-level script name or a dotted path to a subcommand (e.g. `backend.build`)
+    // This is used to resolve references to other scripts in Bones directives and dependencies
+    // This will return an error if the path doesn't lead anywhere or leads to a script that can't be run on its own
+    pub fn get_script_for_path(&self, path: &str) -> Result<&Command, String> {
+        let not_found_err = format!("'{}' is not a path to a script in your Bonnie configuration. This is probably a typo in your Bonnie configuration.", path);
+        let mut parts = path.split('.');
+        // Splitting always gives at least one part
+        let mut command = match self.scripts.get(parts.next().unwrap()) {
+            Some(command) => command,
+            None => return Err(not_found_err),
+        };
+        // Every further part of the path has to be a subcommand of the last
+        for part in parts {
+            command = match command
+                .subcommands
+                .as_ref()
+                .and_then(|subcommands| subcommands.get(part))
+            {
+                Some(subcommand) => subcommand,
+                None => return Err(not_found_err),
+            };
+        }
+        // Scripts with only unordered subcommands have nothing to run on their own
+        if command.subcommands.is_some() && command.order.is_none() && command.cmd.is_none() {
+            return Err(format!("The script '{}' only has unordered subcommands, so it can't be run on its own. Please reference one of its subcommands instead (e.g. '{}.<subcommand>').", path, path));
+        }
+
+        Ok(command)
+    }
+    // Checks that every dependency in the config can be resolved and that no dependencies form a cycle
+    // This is done at parse time so problems are caught even in scripts that aren't being run
+    pub fn validate_deps(&self) -> Result<(), String> {
+        // Gets the paths of every command that has dependencies (including subcommands)
+        fn get_commands_with_deps<'a>(
+            scripts: &'a Scripts,
+            prefix: &str,
+            commands: &mut Vec<(String, &'a Command)>,
+        ) {
+            for (name, command) in scripts.iter() {
+                let path = prefix.to_string() + name;
+                if !command.deps.is_empty() {
+                    commands.push((path.clone(), command));
+                }
+                if let Some(subcommands) = &command.subcommands {
+                    get_commands_with_deps(subcommands, &(path + "."), commands);
+                }
+            }
+        }
+
+        let mut commands = Vec::new();
+        get_commands_with_deps(&self.scripts, "", &mut commands);
+        for (path, command) in commands {
+            self.get_dependency_order(&path, command)?;
+        }
+
+        Ok(())
+    }
+    // Gets the order in which the dependencies of the command at the given path should be run
+    // Dependencies of dependencies come first, and each dependency appears only once, even if several things depend on it
+    // This will return an error if a dependency can't be found or if dependencies form a cycle
+    pub fn get_dependency_order(
+        &self,
+        path: &str,
+        command: &Command,
+    ) -> Result<Vec<String>, String> {
+        // This works depth-first, keeping track of the dependencies we're in the middle of resolving to catch cycles
+        fn add_deps(
+            cfg: &Config,
+            path: &str,
+            command: &Command,
+            stack: &mut Vec<String>,
+            order: &mut Vec<String>,
+        ) -> Result<(), String> {
+            stack.push(path.to_string());
+            for dep in command.deps.iter() {
+                if let Some(idx) = stack.iter().position(|stack_path| stack_path == dep) {
+                    let mut cycle = stack[idx..].to_vec();
+                    cycle.push(dep.to_string());
+                    return Err(format!("Error in parsing Bonnie configuration file: script dependencies form a cycle ({}). This would never finish, so please break the cycle in your Bonnie configuration.", cycle.join(" -> ")));
+                }
+                // Anything already in the order is also being run, so it doesn't need to be run again
+                if order.contains(dep) {
+                    continue;
+                }
+                let dep_command = match cfg.get_script_for_path(dep) {
+                    Ok(dep_command) => dep_command,
+                    Err(err) => {
+                        return Err(format!(
+                        "Error in resolving the dependency '{}' of the '{}' script/subscript: {}",
+                        dep, path, err
+                    ))
+                    }
+                };
+                add_deps(cfg, dep, dep_command, stack, order)?;
+                order.push(dep.to_string());
+            }
+            stack.pop();
+
+            Ok(())
+        }
+
+        let mut order = Vec::new();
+        add_deps(self, path, command, &mut Vec::new(), &mut order)?;
+
+        Ok(order)
+    }
     // Provides a documentation message for this configuration
     // If a single command name is given, only it will be documented
     pub fn document(&self, cmd_to_doc: Option<String>) -> Result<String, String> {
@@ -114,19 +280,21 @@ Version: {}",
 
         let msg;
         if let Some(cmd_name) = cmd_to_doc {
-            let cmd = self.scripts.get(&cmd_name);
-            let cmd = match cmd {
+            let cmd = get_script_by_name_or_alias(&self.scripts, &cmd_name);
+            let (cmd_name, cmd) = match cmd {
+                // Private scripts are treated as if they don't exist here
+                Some((_, cmd)) if cmd.private => return Err(format!("Command '{}' not found. You can see all supported commands by running `bonnie help`.", cmd_name)),
                 Some(cmd) => cmd,
                 None => return Err(format!("Command '{}' not found. You can see all supported commands by running `bonnie help`.", cmd_name))
             };
-            msg = cmd.document(&cmd_name);
+            msg = cmd.document(cmd_name);
         } else {
             // Loop through every command and document it
             let mut msgs = Vec::new();
             // Sort the subcommands alphabetically
             let mut cmds: Vec<(&String, &Command)> = self.scripts.iter().collect();
-            cmds.sort_by(|(name, _), (name2, _)| name.cmp(name2));
-            for (cmd_name, cmd) in cmds {
+            cmds.sort_by_key(|(name, _)| *name);
+            for (cmd_name, cmd) in cmds.into_iter().filter(|(_, cmd)| !cmd.private) {
                 msgs.push(cmd.document(cmd_name));
             }
 
@@ -155,6 +323,26 @@ Version: {}",
 
         Ok(format!("{}\n\n{}", meta, spaced_msg))
     }
+    // Graphs the ordered subcommands of the command requested by the given arguments in the given format
+    // The arguments are interpreted in the same way as when running a command, so nested subcommands can be graphed too
+    pub fn graph(&self, args: &[String], format: GraphFormat) -> Result<String, String> {
+        let (command, command_name, _) = self.get_command_for_args(args)?;
+
+        graph_command(&command_name, command, self, format)
+    }
+}
+// Other projects (each with their own Bonnie configuration) that scripts can be run across with `bonnie --all`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Workspace {
+    pub members: Vec<String>, // Glob patterns for the member directories, relative to the directory of the root config
+    pub parallel: bool,
+}
+// What to do when a script is given more arguments than it uses (i.e. it has no `%%` to insert the rest)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SurplusArgs {
+    Error,
+    Warn,
 }
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct DefaultShell {
@@ -169,78 +357,168 @@ pub struct Shell {
     pub parts: Vec<String>,
     pub delimiter: String,
 }
-pub type TargetString = String; // A target like `linux` or `x86_64-unknown-linux-musl` (see `rustup` targets)
+pub type TargetString = String; // A target like `linux`, `unix`, `aarch64-linux`, or `x86_64-unknown-linux-musl` (see `Target`)
 pub type Scripts = HashMap<String, Command>;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Command {
     pub args: Vec<String>,
     pub env_vars: Vec<String>,
+    pub secret_env_vars: Vec<String>, // These are interpolated like `env_vars`, but their values are masked whenever Bonnie prints a command
+    pub env_files: Vec<String>, // These are only loaded when this script is run (this includes those of any parent scripts)
     pub subcommands: Option<Scripts>, // Subcommands are fully-fledged commands (mostly)
     pub order: Option<BonesDirective>, // If this is specified, subcomands must not specify the `args` property, it may be specified at the top-level of this script as a sibling of `order`
     pub cmd: Option<CommandWrapper>,   // If subcommands are provided, a root command is optional
     pub description: Option<String>,   // This will be rendered in the config's help page
+    pub finally: Vec<String>, // Cleanup steps that will always be run after ordered subcommands, however they ended
+    pub deps: Vec<String>,    // Paths to scripts that must be run (once each) before this one
+    pub aliases: Vec<String>, // Other names this can be invoked by (these are validated not to clash with anything at the same level)
+    pub private: bool, // If this is set, this can't be run directly or seen in the help page, but other scripts can still use it
+    pub when: Option<Condition>, // If this is given and isn't met when the command is prepared, it'll be skipped (or `else_cmd` will be run instead)
+    pub else_cmd: Option<CommandWrapper>, // What to run instead if `when` isn't met
 }
 impl Command {
     // Prepares a command by interpolating everything and resolving shell/tagret logic
-    // This requires the name of the command and the file's `DefaultShell` configuration
+    // This requires the name of the command and the config it's from (for the default shell and for resolving other scripts referenced in directives)
     // This interpolates arguments and environment variables
     // This returns a `BonesCommand` to be executed
     // This accepts an output for warnings (extracted for testing)
+    // If this command has dependencies, they'll be run first (without any arguments), and the first failure will stop everything
+    // If arguments are given beyond those any stage takes (including scripts referenced in directives) and no stage inserts them with `%%`, they'd be silently dropped, so that's an error (or a warning if configured)
     pub fn prepare(
         &self,
         name: &str,
         prog_args: &[String],
-        default_shell: &DefaultShell,
+        cfg: &Config,
+        output: &mut impl std::io::Write,
     ) -> Result<Bone, String> {
-        let bone = self.prepare_internal(name, prog_args, default_shell, None)?;
+        // We keep track of the scripts being prepared through references so we can catch cycles
+        let bone = self.prepare_internal(
+            name,
+            prog_args,
+            cfg,
+            None,
+            &mut vec![(self, name.to_string())],
+        )?;
+        // We can only know if the arguments were used once every stage (including any ordered subcommands and the scripts they reference) has been prepared
+        let num_args_used = bone.num_args_used();
+        if prog_args.len() > num_args_used && !bone.uses_remaining_args() {
+            let surplus_args: Vec<String> = prog_args[num_args_used..]
+                .iter()
+                .map(|arg| format!("'{}'", arg))
+                .collect();
+            match cfg.surplus_args {
+                SurplusArgs::Error => return Err(format!("The command '{}' was given arguments it doesn't use: {}. Nothing in it inserts extra arguments with `%%`, so these would be ignored. Please remove them, or set `surplus_args = \"warn\"` in your Bonnie configuration to ignore them with a warning.", name, surplus_args.join(", "))),
+                SurplusArgs::Warn => writeln!(output, "Warning: ignoring arguments the command '{}' doesn't use: {}.", name, surplus_args.join(", ")).expect("Failed to write warning."),
+            }
+        }
+        if bone.has_unquoted_args() {
+            writeln!(output, "Warning: the command '{}' runs in a shell Bonnie doesn't know how to quote arguments for (only POSIX shells and PowerShell are supported), so arguments have been inserted as they are. Make sure they don't contain anything your shell will interpret, or use a supported shell.", name).expect("Failed to write warning.");
+        }
+        // Only the dependencies of the command being run directly are resolved (not those of scripts referenced in its directive)
+        let dep_order = cfg.get_dependency_order(name, self)?;
+        if dep_order.is_empty() {
+            return Ok(bone);
+        }
+        let mut steps = Vec::new();
+        for dep in dep_order {
+            // Validation on parsing means this will work
+            let dep_command = cfg.get_script_for_path(&dep)?;
+            let dep_bone = dep_command.prepare_internal(
+                &dep,
+                &[],
+                cfg,
+                None,
+                &mut vec![(dep_command, dep.to_string())],
+            )?;
+            steps.push((dep, dep_bone));
+        }
+        steps.push((name.to_string(), bone));
 
-        Ok(bone)
+        Ok(Bone::Sequence(steps))
     }
     // This is the internal command preparation logic, which is called recursively.
     // This also takes top-level arguments for recursing on ordered subcommands
-    fn prepare_internal(
-        &self,
+    // The stack holds every script currently being prepared because it was referenced in a directive (and the original command), which is used to detect recursion cycles
+    fn prepare_internal<'a>(
+        &'a self,
         name: &str,
         prog_args: &[String],
-        default_shell: &DefaultShell,
+        cfg: &'a Config,
         top_level_args: Option<&[String]>,
+        stack: &mut Vec<(&'a Command, String)>,
     ) -> Result<Bone, String> {
         let args = match top_level_args {
             Some(args) => args,
             None => &self.args,
         };
         let at_top_level = top_level_args.is_none();
-        if matches!(self.subcommands, None)
-            || (matches!(self.subcommands, Some(_)) && matches!(self.cmd, Some(_)))
-        {
-            // We have either a direct command or a parent command that has irrelevant subcommands, either way we're interpolating into `cmd`
+        // The script's own environment variable files are loaded first so its condition sees the same environment it would
+        let from_files = load_script_env_files(&self.env_files)?;
+        // If this has a condition that isn't met, it's skipped, or its `else` command is run instead (of `cmd` or any ordered subcommands)
+        let condition_met = self.when.as_ref().is_none_or(|when| {
+            when.is_met(&cfg.config_dir, |name| {
+                from_files
+                    .get(name)
+                    .cloned()
+                    .or_else(|| env::var(name).ok())
+            })
+        });
+        if !condition_met && self.else_cmd.is_none() {
+            return Ok(Bone::Skipped);
+        }
+        if !condition_met || self.subcommands.is_none() || self.cmd.is_some() {
+            // We have either a direct command or a parent command that has irrelevant subcommands, either way we're interpolating into `cmd` (or `else`)
             // Get the vector of command wrappers
             // Assuming the transformation logic works, an error can't occur here
-            let command_wrapper = self.cmd.as_ref().unwrap();
+            let command_wrapper = match &self.else_cmd {
+                Some(else_cmd) if !condition_met => else_cmd,
+                _ => self.cmd.as_ref().unwrap(),
+            };
             // Interpolate for each individual command
             // We have to do this in a for loop for `?`
-            let mut cmd_strs: Vec<String> = Vec::new();
-            let (cmds, shell) = command_wrapper.get_commands_and_shell(default_shell);
-            for cmd_str in cmds {
-                let with_env_vars = Command::interpolate_env_vars(&cmd_str, &self.env_vars)?;
-                let (with_args, remaining_args) =
-                    Command::interpolate_specific_args(&with_env_vars, name, args, prog_args)?;
-                let ready_cmd =
-                    Command::interpolate_remaining_arguments(&with_args, &remaining_args);
-                cmd_strs.push(ready_cmd);
+            let mut cmd_parts: Vec<CommandPart> = Vec::new();
+            let (cmds, shell) = command_wrapper.get_commands_and_shell(&cfg.default_shell);
+            // Whether or not there are too many arguments is checked once everything's been prepared (in case another stage or subcommand uses `%%`)
+            let values = Values::new(
+                name,
+                args,
+                ScriptEnv {
+                    env_vars: &self.env_vars,
+                    secret_env_vars: &self.secret_env_vars,
+                    from_files,
+                },
+                prog_args,
+                Builtins::new(name, &cfg.config_dir),
+                Quoting::for_shell(&shell),
+            )?;
+            for (idx, cmd_str) in cmds.iter().enumerate() {
+                // We join every stage of the command into one, separated by the given delimiters
+                if idx > 0 {
+                    cmd_parts.push(CommandPart::Text(shell.delimiter.to_string()));
+                }
+                cmd_parts.extend(interpolate(cmd_str, &values)?);
             }
 
             Ok(
                 // This does not contain recursive `BonesCommands`, so it's `Bone::Simple`
                 Bone::Simple(BonesCore {
-                    // We join every stage of the command into one, separated by the given delimiters
-                    cmd: cmd_strs.join(&shell.delimiter),
-                    // The shell is then just the vector of executable and arguments
-                    shell: shell.parts.to_vec(),
+                    cmd: cmd_parts,
+                    // The shell is then just the vector of executable and arguments (any environment variables from `[vars]` are resolved now)
+                    shell: shell
+                        .parts
+                        .iter()
+                        .map(|part| resolve_env_refs(part, |name| values.get_env(name)))
+                        .collect::<Result<Vec<String>, String>>()?,
+                    uses_remaining_args: values.used_remaining_args(),
+                    has_unquoted_args: values.has_unquoted_args(),
+                    num_args: args.len(),
+                    posix_shell: Quoting::for_shell(&shell) == Quoting::Posix,
+                    secrets: values.secrets(),
+                    env: values.into_env_from_files(),
                 }),
             )
-        } else if matches!(self.subcommands, Some(_)) && matches!(self.order, Some(_)) {
+        } else if let (Some(subcommands), Some(order)) = (&self.subcommands, &self.order) {
             // First, we resolve all the subcommands to vectors of strings to actually run
             let mut cmds: HashMap<String, Bone> = HashMap::new();
             // Now we run checks on whether the correct number of arguments have been provided if we're at the very top level
@@ -256,135 +534,81 @@ impl Command {
                     )
                 );
             }
-            // We `.unwrap()` here because we know more than the compiler
-            for (subcommand_name, subcommand) in self.subcommands.as_ref().unwrap().iter() {
+            for (subcommand_name, subcommand) in subcommands.iter() {
                 // Parse the subcommand
                 // We parse in the top-level arguments because ordered subcommands can't take their own, they inherit from this level (or the level this level inherits from, etc.)
+                // Subcommands are prepared with their full path so it can be interpolated as `%[script]`
                 let cmd = subcommand.prepare_internal(
-                    subcommand_name,
+                    &format!("{}.{}", name, subcommand_name),
                     prog_args,
-                    default_shell,
+                    cfg,
                     Some(args),
+                    stack,
                 )?;
                 cmds.insert(subcommand_name.to_string(), cmd);
             }
+            // Any names in the directive (or the cleanup steps) that aren't subcommands are references to other scripts (possibly as dotted paths like `backend.build`)
+            let mut command_names = order.command_names();
+            command_names.extend(self.finally.iter().map(|name| name.as_str()));
+            for command_name in command_names {
+                if cmds.contains_key(command_name) {
+                    continue;
+                }
+                let referenced = match cfg.get_script_for_path(command_name) {
+                    Ok(referenced) => referenced,
+                    Err(err) => return Err(format!("Error in preparing Bones directive: '{}' is not a subcommand, and resolving it as another script failed. {}", command_name, err))
+                };
+                // If we're already preparing this script further up, we'd never finish
+                if let Some(idx) = stack
+                    .iter()
+                    .position(|(command, _)| std::ptr::eq(*command, referenced))
+                {
+                    let mut cycle: Vec<&str> =
+                        stack[idx..].iter().map(|(_, name)| name.as_str()).collect();
+                    cycle.push(command_name);
+                    return Err(format!("Error in preparing Bones directive: scripts referenced in directives form a cycle ({}). This would never finish, so please break the cycle in your Bonnie configuration.", cycle.join(" -> ")));
+                }
+                // Referenced scripts are prepared as if they'd been run directly, so they take their own arguments
+                stack.push((referenced, command_name.to_string()));
+                let cmd = referenced.prepare_internal(command_name, prog_args, cfg, None, stack)?;
+                stack.pop();
+                cmds.insert(command_name.to_string(), cmd);
+            }
 
             // Now we return a complex `Bone` (because it contains a `BonesCommand` with a directive)
-            Ok(Bone::Complex(
-                BonesCommand::new(self.order.as_ref().unwrap(), cmds), // We know more than the compiler by the check above
-            ))
+            Ok(Bone::Complex(BonesCommand::new(
+                order,
+                cmds,
+                self.finally.to_vec(),
+            )))
         } else {
             // This should not be possible!
             panic!("Critical logic failure in preparing command. You should report this as a bug.");
         }
     }
-    // Interpolates specific arguments (doesn't handle `%%`)
-    // This takes a string to interpolate into and doesn't take `self` so the order is open
-    // This returns the readied command string and the remaining arguments or an error if an argument couldn't be substituted in
-    // Errors for when the argument can't be interpolated can be silenced for ordered subcommands (which have a universal argument list for many subcommands)
-    fn interpolate_specific_args(
-        cmd_str: &str,
-        name: &str,
-        args: &[String],
-        prog_args: &[String],
-    ) -> Result<(String, Vec<String>), String> {
-        // Check if the correct number of arguments was provided
-        // Even if we're inserting the rest later, we still need the mandatory ones
-        if args.len() > prog_args.len() {
-            return Err(
-                format!(
-                    "The command '{command}' requires {num_required_args} argument(s), but {num_given_args} argument(s) were provided (too few). Please provide all the required arguments.",
-                    command=name,
-                    num_required_args=args.len(),
-                    num_given_args=&prog_args.len()
-                )
-            );
-        }
-        // We don't warn if there are too many and we're not inserting the rest with `%%` later because that would mean checking every potential subcommand for `%%` as well if they exist
-        let mut with_args = cmd_str.to_string();
-        // We need to know the index so we can correlate to the index of the argument in `args`
-        for (idx, arg) in args.iter().enumerate() {
-            // The arrays are the same length, see above check
-            // All arguments are shown in the command string as `%name` or the like, so we get that whole string
-            let given_value = &prog_args[idx];
-            let arg_with_sign = "%".to_string() + arg;
-            let new_command = with_args.replace(&arg_with_sign, given_value);
-            // We don't check if we changed something because that doesn't work for multistage or ordered subcommands
-            with_args = new_command;
-        }
-        // Get the program args after a certain point so they can be inserted with `%%` if necessary
-        // We do this by getting the part of slice after the specific arguments
-        let (_, remaining_args) = prog_args.split_at(args.len());
-
-        Ok((with_args, remaining_args.to_vec())) // FIXME
-    }
-    // Interpolates environment variables
-    // This takes a string to interpolate into, the environment variables to interpolate, and the name of the command
-    // This doesn't take `self` so the order is open
-    // This returns the readied command string only, or an error relating to environment variable loading
-    fn interpolate_env_vars(cmd_str: &str, env_vars: &[String]) -> Result<String, String> {
-        let mut with_env_vars = cmd_str.to_string();
-        for env_var_name in env_vars.iter() {
-            // Load the environment variable
-            let env_var = env::var(env_var_name);
-            let env_var = match env_var {
-                Ok(env_var) => env_var,
-                Err(_) => return Err(format!("The environment variable '{}' couldn't be loaded. This means it either hasn't been defined (you may need to load another environment variable file) or contains invalid characters.", env_var_name))
-            };
-            // Interpolate it into the command itself
-            let to_replace = "%".to_string() + env_var_name;
-            let new_command = with_env_vars.replace(&to_replace, &env_var);
-            // We don't check if we changed something because that doesn't work for multistage or ordered subcommands
-            with_env_vars = new_command;
-        }
-
-        Ok(with_env_vars)
-    }
-    // Interpolates all the given arguments at `%%` if it exists
-    // This takes a string to interpolate into and doesn't take `self` so the order is open
-    // This returns the readied command string only
-    fn interpolate_remaining_arguments(cmd_str: &str, prog_args: &[String]) -> String {
-        // This is just a simple `replace` operation for the operator `%%`
-        // Split the command by the block insertion operator `%%`
-        let mut interpolated = String::new();
-        let split_on_operator: Vec<&str> = cmd_str.split("%%").collect();
-        for (idx, part) in split_on_operator.iter().enumerate() {
-            if idx == split_on_operator.len() - 1 {
-                // This is the last element, there's no operator after this
-                interpolated.push_str(part);
-            } else if part.ends_with('\\') {
-                // This part ends with `\`, meaning the operator was escaped
-                // We just give the `%%` back
-                // We only give back the part up until the escape character
-                interpolated.push_str(&part[0..part.len() - 1]);
-                interpolated.push_str("%%");
-            } else {
-                // There's a legitimate operator that should be at the end of this part
-                // We push the program's arguments
-                interpolated.push_str(part);
-                interpolated.push_str(&prog_args.join(" "));
-            }
-        }
-
-        interpolated
-    }
     // Gets a documentation message for this command based on its metadata and the `desc` properties
     fn document(&self, name: &str) -> String {
         let mut msgs = Vec::new();
         // Get the user-given docs (if they exist)
+        // Any environment variables from `[vars]` are resolved now, but an unset one shouldn't stop the help page from being shown
         let doc = match &self.description {
-            Some(desc) => desc.to_string(),
+            Some(desc) => resolve_env_refs(desc, |name| env::var(name).ok())
+                .unwrap_or_else(|_| desc.to_string()),
             None => String::from("no 'desc' property set"),
         };
 
         // Set up the left side (command name and some arguments info)
         let mut left = String::new();
         // Environment variables (before the command name)
-        for env_var in &self.env_vars {
+        for env_var in self.env_vars.iter().chain(self.secret_env_vars.iter()) {
             left += &format!("<{}> ", env_var);
         }
         // Command name
         left += name;
+        // Aliases (right next to the name, since they're interchangeable with it)
+        for alias in &self.aliases {
+            left += &format!(", {}", alias);
+        }
         // Arguments (after the command name)
         for arg in &self.args {
             left += &format!(" <{}>", arg);
@@ -401,8 +625,8 @@ impl Command {
         if let Some(subcommands_map) = &self.subcommands {
             // Sort the subcommands alphabetically
             let mut subcommands_iter: Vec<(&String, &Command)> = subcommands_map.iter().collect();
-            subcommands_iter.sort_by(|(name, _), (name2, _)| name.cmp(name2));
-            for (cmd_name, cmd) in subcommands_iter {
+            subcommands_iter.sort_by_key(|(name, _)| *name);
+            for (cmd_name, cmd) in subcommands_iter.into_iter().filter(|(_, cmd)| !cmd.private) {
                 let subcmd_doc = cmd.document(cmd_name);
                 msgs.push(
                     // We add four spaces in front of every line (that way it works recursively for nested subcommands)
@@ -424,22 +648,9 @@ impl CommandWrapper {
     // Gets the command to run, interpolated into a shell from the ambient OS information
     // This critically resolves which target we're running on
     fn get_commands_and_shell(&self, default_shell: &DefaultShell) -> (Vec<String>, Shell) {
-        // Get the current target (unfortuantely we can't actually get the value out of `cfg!` yet...)
-        // If the user needs to set custom commands based on target arch etc., they can write a script for it, this is exhaustive enough!
-        let running_on = match true {
-            _ if cfg!(target_os = "windows") => "windows",
-            _ if cfg!(target_os = "macos") => "macos",
-            _ if cfg!(target_os = "ios") => "ios",
-            _ if cfg!(target_os = "linux") => "linux",
-            _ if cfg!(target_os = "android") => "android",
-            _ if cfg!(target_os = "freebsd") => "freebsd",
-            _ if cfg!(target_os = "dragonfly") => "dragonfly",
-            _ if cfg!(target_os = "openbsd") => "openbsd",
-            _ if cfg!(target_os = "netbsd") => "netbsd",
-            _ => "unknown", // If they want to, the user could actually specify something for this (like begging to be run somewhere that makes sense)
-        };
-        // See if that target is specified explicitly
-        let target_specific_command_core = self.targets.get(running_on);
+        let running_on = Target::current();
+        // See if that target is specified explicitly (at any level of specificity)
+        let target_specific_command_core = running_on.select(&self.targets);
         let command_core = match target_specific_command_core {
             Some(command_core) => command_core,
             None => &self.generic,
@@ -453,7 +664,7 @@ impl CommandWrapper {
                 // If a particular shell has been configured for the current target, use that
                 // Otherwise, use the generic
                 // Remember that the schema transformation inserts program-level defaults if they aren't configured for the file by the user
-                let target_specific_shell = default_shell.targets.get(running_on);
+                let target_specific_shell = running_on.select(&default_shell.targets);
                 match target_specific_shell {
                     Some(default_shell) => default_shell,
                     None => &default_shell.generic,
@@ -464,6 +675,56 @@ impl CommandWrapper {
         (cmd.to_vec(), shell.clone())
     }
 }
+// A condition that must be met for a command to be run (every part given must hold)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Condition {
+    pub env: Option<String>, // An environment variable that must be set and non-empty (or `NAME=value` for a specific value), including those from the script's own environment variable files
+    pub file_exists: Option<String>, // A path (relative to the config's directory, included files' paths are made relative to it when they're merged) that must exist
+    pub command_exists: Option<String>, // An executable that must be on the `PATH`
+}
+impl Condition {
+    // Checks if this condition is currently met, resolving relative paths against the given config directory and getting environment variables with the given function
+    pub fn is_met(&self, config_dir: &str, get_env: impl Fn(&str) -> Option<String>) -> bool {
+        let env_met = match &self.env {
+            Some(env) => match env.split_once('=') {
+                Some((name, value)) => get_env(name).is_some_and(|env_var| env_var == value),
+                None => get_env(env).is_some_and(|env_var| !env_var.is_empty()),
+            },
+            None => true,
+        };
+        let file_exists_met = match &self.file_exists {
+            Some(file) => Path::new(config_dir).join(file).exists(),
+            None => true,
+        };
+        let command_exists_met = match &self.command_exists {
+            Some(command) => command_exists(command),
+            None => true,
+        };
+
+        env_met && file_exists_met && command_exists_met
+    }
+}
+// Checks if the given executable can be found (either directly if it's a path or on the `PATH`)
+fn command_exists(command: &str) -> bool {
+    if command.contains(&['/', '\\'][..]) {
+        return Path::new(command).is_file();
+    }
+    // On Windows, executables can be given without their extensions
+    let extensions: Vec<String> = match env::var("PATHEXT") {
+        Ok(pathext) if cfg!(windows) => pathext.split(';').map(|ext| ext.to_string()).collect(),
+        _ => Vec::new(),
+    };
+    let paths = match env::var_os("PATH") {
+        Some(paths) => paths,
+        None => return false,
+    };
+    env::split_paths(&paths).any(|dir| {
+        dir.join(command).is_file()
+            || extensions
+                .iter()
+                .any(|ext| dir.join(format!("{}{}", command, ext)).is_file())
+    })
+}
 // This is the lowest level of command specification, there is no more recursion allowed here (thus avoiding circularity)
 // Actual command must be specified here are strings (with potential interpolation of arguments and environment variables)
 // This can also define which shell the command will use
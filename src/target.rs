@@ -0,0 +1,203 @@
+// This file contains the logic for working out which target we're running on, and which of the targets given in a config that matches best
+// Targets can be given at several levels of specificity (e.g. `x86_64-unknown-linux-musl`, `x86_64-linux`, `linux-musl`, `linux`, `x86_64`, `musl`, or `unix`)
+// The target can be overridden with the `BONNIE_TARGET` environment variable (e.g. to test Windows scripts from Linux or to see what would run there with `--dry-run`)
+
+use std::collections::HashMap;
+use std::env;
+
+// The OS names that can appear in target triples, with the names Bonnie uses for them (the first match in a triple wins, so `android` beats `linux`)
+const KNOWN_OSES: [(&str, &str); 10] = [
+    ("windows", "windows"),
+    ("darwin", "macos"),
+    ("macos", "macos"),
+    ("ios", "ios"),
+    ("android", "android"),
+    ("linux", "linux"),
+    ("freebsd", "freebsd"),
+    ("dragonfly", "dragonfly"),
+    ("openbsd", "openbsd"),
+    ("netbsd", "netbsd"),
+];
+
+// A description of a target that scripts can be selected by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub triple: String, // The full target triple (e.g. `x86_64-unknown-linux-gnu`), or whatever was given in `BONNIE_TARGET`
+    pub os: String,     // e.g. `linux` (this is `unknown` if we don't know it)
+    pub arch: String,   // e.g. `x86_64`
+    pub env: String,    // e.g. `musl` (this may be empty)
+    pub family: String, // `unix` or `windows` (this may be empty)
+}
+impl Target {
+    // Gets the target we're running on, which can be overridden with the `BONNIE_TARGET` environment variable
+    pub fn current() -> Self {
+        match env::var("BONNIE_TARGET") {
+            Ok(target) if !target.is_empty() => Self::parse(&target),
+            _ => Self::host(),
+        }
+    }
+    // Gets the target Bonnie was built for
+    fn host() -> Self {
+        // Unfortuantely we can't actually get the value out of `cfg!` yet...
+        let os = match true {
+            _ if cfg!(target_os = "windows") => "windows",
+            _ if cfg!(target_os = "macos") => "macos",
+            _ if cfg!(target_os = "ios") => "ios",
+            _ if cfg!(target_os = "linux") => "linux",
+            _ if cfg!(target_os = "android") => "android",
+            _ if cfg!(target_os = "freebsd") => "freebsd",
+            _ if cfg!(target_os = "dragonfly") => "dragonfly",
+            _ if cfg!(target_os = "openbsd") => "openbsd",
+            _ if cfg!(target_os = "netbsd") => "netbsd",
+            _ => "unknown", // If they want to, the user could actually specify something for this (like begging to be run somewhere that makes sense)
+        };
+        let target_env = match true {
+            _ if cfg!(target_env = "gnu") => "gnu",
+            _ if cfg!(target_env = "musl") => "musl",
+            _ if cfg!(target_env = "msvc") => "msvc",
+            _ => "",
+        };
+
+        Self {
+            triple: env!("BONNIE_BUILD_TARGET").to_string(),
+            os: os.to_string(),
+            arch: env::consts::ARCH.to_string(),
+            env: target_env.to_string(),
+            family: env::consts::FAMILY.to_string(),
+        }
+    }
+    // Parses a target from a triple like `aarch64-unknown-linux-musl`
+    // A single name like `windows` is taken to be an OS, with the host's architecture
+    // Pairs are taken to be an OS and environment if they start with an OS (like `linux-musl`), or an architecture and OS otherwise (like `aarch64-linux`)
+    pub fn parse(target: &str) -> Self {
+        let parts: Vec<&str> = target.split('-').collect();
+        let leading_os = KNOWN_OSES.iter().find(|(name, _)| *name == parts[0]);
+        let (os, arch, target_env) = if parts.len() == 1 {
+            (
+                target.to_string(),
+                env::consts::ARCH.to_string(),
+                String::new(),
+            )
+        } else if let (2, Some((_, os))) = (parts.len(), leading_os) {
+            (
+                os.to_string(),
+                env::consts::ARCH.to_string(),
+                normalize_env(parts[1]),
+            )
+        } else {
+            let os = KNOWN_OSES
+                .iter()
+                .find(|(name, _)| parts[1..].contains(name))
+                .map_or("unknown", |(_, os)| os);
+            // Only full triples (like `x86_64-unknown-linux-gnu`) have an environment at the end
+            let target_env = match parts.last() {
+                Some(target_env) if parts.len() >= 4 => normalize_env(target_env),
+                _ => String::new(),
+            };
+            (os.to_string(), parts[0].to_string(), target_env)
+        };
+        let family = match os.as_str() {
+            "windows" => "windows",
+            "unknown" => "",
+            _ => "unix",
+        };
+
+        Self {
+            triple: target.to_string(),
+            os,
+            arch,
+            env: target_env,
+            family: family.to_string(),
+        }
+    }
+    // Gets the names this target can be given by in a config, from most to least specific
+    fn candidates(&self) -> Vec<String> {
+        let mut candidates = vec![
+            self.triple.to_string(),
+            format!("{}-{}", self.arch, self.os),
+        ];
+        if !self.env.is_empty() {
+            candidates.push(format!("{}-{}", self.os, self.env));
+        }
+        candidates.push(self.os.to_string());
+        candidates.push(self.arch.to_string());
+        candidates.push(self.env.to_string());
+        candidates.push(self.family.to_string());
+        // Nothing should be matched by an empty name
+        candidates.retain(|candidate| !candidate.is_empty() && !candidate.starts_with('-'));
+
+        candidates
+    }
+    // Gets the value given for the most specific name of this target in the given map (if there is one)
+    pub fn select<'a, T>(&self, targets: &'a HashMap<String, T>) -> Option<&'a T> {
+        self.candidates()
+            .iter()
+            .find_map(|candidate| targets.get(candidate))
+    }
+}
+
+// Normalizes the environment part of a triple (e.g. `musleabihf` is still `musl`)
+fn normalize_env(target_env: &str) -> String {
+    ["musl", "gnu", "msvc"]
+        .iter()
+        .find(|name| target_env.starts_with(*name))
+        .map_or(target_env, |name| name)
+        .to_string()
+}
+
+// Tests for target parsing and selection
+#[test]
+fn parses_full_triples() {
+    let target = Target::parse("aarch64-unknown-linux-musleabihf");
+
+    assert_eq!(target.os, "linux");
+    assert_eq!(target.arch, "aarch64");
+    assert_eq!(target.env, "musl");
+    assert_eq!(target.family, "unix");
+}
+#[test]
+fn parses_short_triples() {
+    let target = Target::parse("x86_64-apple-darwin");
+
+    assert_eq!(target.os, "macos");
+    assert_eq!(target.env, "");
+    assert_eq!(target.family, "unix");
+    assert_eq!(Target::parse("aarch64-linux-android").os, "android");
+}
+#[test]
+fn parses_os_env_pairs() {
+    let target = Target::parse("linux-musl");
+
+    assert_eq!(target.os, "linux");
+    assert_eq!(target.arch, env::consts::ARCH);
+    assert_eq!(target.env, "musl");
+    assert_eq!(target.family, "unix");
+    assert_eq!(Target::parse("aarch64-linux").arch, "aarch64");
+}
+#[test]
+fn selects_most_specific_target() {
+    let target = Target::parse("x86_64-pc-windows-msvc");
+    let mut targets = HashMap::new();
+    targets.insert("windows".to_string(), 1);
+    targets.insert("x86_64".to_string(), 2);
+    assert_eq!(target.select(&targets), Some(&1));
+    targets.insert("windows-msvc".to_string(), 3);
+    assert_eq!(target.select(&targets), Some(&3));
+    targets.insert("x86_64-windows".to_string(), 4);
+    assert_eq!(target.select(&targets), Some(&4));
+    targets.insert("x86_64-pc-windows-msvc".to_string(), 5);
+    assert_eq!(target.select(&targets), Some(&5));
+}
+#[test]
+fn selects_family_last() {
+    let target = Target::parse("x86_64-unknown-linux-gnu");
+    let mut targets = HashMap::new();
+    targets.insert("unix".to_string(), 1);
+    assert_eq!(target.select(&targets), Some(&1));
+    targets.insert("gnu".to_string(), 2);
+    assert_eq!(target.select(&targets), Some(&2));
+    assert_eq!(
+        Target::parse("wasm32-unknown-unknown").select(&targets),
+        None
+    );
+}
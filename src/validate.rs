@@ -0,0 +1,241 @@
+// This file contains a validation pass over raw configs that finds keys Bonnie doesn't know about
+// The raw schema is made of untagged enums, so Serde silently ignores typos like `descr` (or fails with an unhelpful error), this catches them
+// This works on a generic value so it's the same for every config format
+
+use serde_json::{Map, Value};
+
+const CONFIG_KEYS: [&str; 9] = [
+    "version",
+    "env_files",
+    "include",
+    "default_shell",
+    "workspace",
+    "vars",
+    "strict",
+    "surplus_args",
+    "scripts",
+];
+// Included files can only define these (everything else comes from the main configuration file)
+const INCLUDED_CONFIG_KEYS: [&str; 3] = ["include", "env_files", "scripts"];
+const DEFAULT_SHELL_KEYS: [&str; 2] = ["generic", "targets"];
+const SHELL_KEYS: [&str; 2] = ["parts", "delimiter"];
+const WORKSPACE_KEYS: [&str; 2] = ["members", "parallel"];
+const COMMAND_KEYS: [&str; 14] = [
+    "args",
+    "env_vars",
+    "secret_env_vars",
+    "env_files",
+    "subcommands",
+    "order",
+    "cmd",
+    "desc",
+    "finally",
+    "deps",
+    "aliases",
+    "private",
+    "when",
+    "else",
+];
+const CONDITION_KEYS: [&str; 3] = ["env", "file_exists", "command_exists"];
+const COMMAND_WRAPPER_KEYS: [&str; 2] = ["generic", "targets"];
+const COMMAND_CORE_KEYS: [&str; 2] = ["exec", "shell"];
+
+// Finds all the keys in the given raw config that Bonnie doesn't know about
+// This returns a description of each one with its full path (e.g. `scripts.deploy.descr`) and a suggested replacement if there's a similar valid key
+pub fn find_unknown_keys(cfg: &Value) -> Vec<String> {
+    check_config(cfg, &CONFIG_KEYS)
+}
+// Like `find_unknown_keys`, but for an included file, which can only have some of the keys of a full config
+pub fn find_unknown_keys_in_included(cfg: &Value) -> Vec<String> {
+    check_config(cfg, &INCLUDED_CONFIG_KEYS)
+}
+
+fn check_config(cfg: &Value, allowed: &[&str]) -> Vec<String> {
+    let mut unknown_keys = Vec::new();
+    if let Value::Object(cfg) = cfg {
+        check_keys(cfg, allowed, "", &mut unknown_keys);
+        // Anything that isn't allowed here has already been reported, so we don't look inside it
+        let is_allowed = |key: &str| allowed.contains(&key);
+        if let Some(default_shell) = cfg
+            .get("default_shell")
+            .filter(|_| is_allowed("default_shell"))
+        {
+            check_default_shell(default_shell, "default_shell", &mut unknown_keys);
+        }
+        if let Some(Value::Object(workspace)) =
+            cfg.get("workspace").filter(|_| is_allowed("workspace"))
+        {
+            check_keys(workspace, &WORKSPACE_KEYS, "workspace", &mut unknown_keys);
+        }
+        if let Some(Value::Object(scripts)) = cfg.get("scripts") {
+            check_scripts(scripts, "scripts", &mut unknown_keys);
+        }
+    }
+
+    unknown_keys
+}
+
+// Checks that every key in the given map is allowed, adding any that aren't to the given list of unknown keys
+fn check_keys(
+    map: &Map<String, Value>,
+    allowed: &[&str],
+    path: &str,
+    unknown_keys: &mut Vec<String>,
+) {
+    for key in map.keys() {
+        if allowed.contains(&key.as_str()) {
+            continue;
+        }
+        let mut msg = format!("unknown key '{}'", join_path(path, key));
+        if let Some(suggestion) = suggest_key(key, allowed) {
+            msg += &format!(" (did you mean '{}'?)", join_path(path, suggestion));
+        }
+        unknown_keys.push(msg);
+    }
+}
+fn check_default_shell(default_shell: &Value, path: &str, unknown_keys: &mut Vec<String>) {
+    if let Value::Object(default_shell) = default_shell {
+        // The simple form of a shell can be a table too, but only with its own keys
+        if default_shell.contains_key("parts") || default_shell.contains_key("delimiter") {
+            check_keys(default_shell, &SHELL_KEYS, path, unknown_keys);
+            return;
+        }
+        check_keys(default_shell, &DEFAULT_SHELL_KEYS, path, unknown_keys);
+        if let Some(generic) = default_shell.get("generic") {
+            check_shell(generic, &join_path(path, "generic"), unknown_keys);
+        }
+        if let Some(Value::Object(targets)) = default_shell.get("targets") {
+            let targets_path = join_path(path, "targets");
+            for (target_name, shell) in targets.iter() {
+                check_shell(shell, &join_path(&targets_path, target_name), unknown_keys);
+            }
+        }
+    }
+}
+fn check_shell(shell: &Value, path: &str, unknown_keys: &mut Vec<String>) {
+    if let Value::Object(shell) = shell {
+        check_keys(shell, &SHELL_KEYS, path, unknown_keys);
+    }
+}
+fn check_scripts(scripts: &Map<String, Value>, path: &str, unknown_keys: &mut Vec<String>) {
+    for (script_name, command) in scripts.iter() {
+        check_command(command, &join_path(path, script_name), unknown_keys);
+    }
+}
+fn check_command(command_value: &Value, path: &str, unknown_keys: &mut Vec<String>) {
+    if let Value::Object(command) = command_value {
+        // A script can also be given directly as a command for different targets or with a custom shell
+        let is_command_wrapper = !command
+            .keys()
+            .any(|key| COMMAND_KEYS.contains(&key.as_str()))
+            && command.keys().any(|key| {
+                COMMAND_WRAPPER_KEYS.contains(&key.as_str())
+                    || COMMAND_CORE_KEYS.contains(&key.as_str())
+            });
+        if is_command_wrapper {
+            check_command_wrapper(command_value, path, unknown_keys);
+            return;
+        }
+        check_keys(command, &COMMAND_KEYS, path, unknown_keys);
+        if let Some(cmd) = command.get("cmd") {
+            check_command_wrapper(cmd, &join_path(path, "cmd"), unknown_keys);
+        }
+        if let Some(Value::Object(when)) = command.get("when") {
+            check_keys(
+                when,
+                &CONDITION_KEYS,
+                &join_path(path, "when"),
+                unknown_keys,
+            );
+        }
+        if let Some(else_cmd) = command.get("else") {
+            check_command_wrapper(else_cmd, &join_path(path, "else"), unknown_keys);
+        }
+        if let Some(Value::Object(subcommands)) = command.get("subcommands") {
+            check_scripts(subcommands, &join_path(path, "subcommands"), unknown_keys);
+        }
+    }
+}
+fn check_command_wrapper(command_wrapper: &Value, path: &str, unknown_keys: &mut Vec<String>) {
+    if let Value::Object(command_wrapper) = command_wrapper {
+        if !command_wrapper.contains_key("generic") && !command_wrapper.contains_key("targets") {
+            check_command_core(command_wrapper, path, unknown_keys);
+            return;
+        }
+        check_keys(command_wrapper, &COMMAND_WRAPPER_KEYS, path, unknown_keys);
+        if let Some(Value::Object(generic)) = command_wrapper.get("generic") {
+            check_command_core(generic, &join_path(path, "generic"), unknown_keys);
+        }
+        if let Some(Value::Object(targets)) = command_wrapper.get("targets") {
+            let targets_path = join_path(path, "targets");
+            for (target_name, command_core) in targets.iter() {
+                if let Value::Object(command_core) = command_core {
+                    check_command_core(
+                        command_core,
+                        &join_path(&targets_path, target_name),
+                        unknown_keys,
+                    );
+                }
+            }
+        }
+    }
+}
+fn check_command_core(
+    command_core: &Map<String, Value>,
+    path: &str,
+    unknown_keys: &mut Vec<String>,
+) {
+    check_keys(command_core, &COMMAND_CORE_KEYS, path, unknown_keys);
+    if let Some(shell) = command_core.get("shell") {
+        check_shell(shell, &join_path(path, "shell"), unknown_keys);
+    }
+}
+
+// Joins a key onto a dotted path
+fn join_path(path: &str, key: &str) -> String {
+    match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    }
+}
+
+// Suggests the allowed key most similar to the given unknown one, if any of them are close enough to plausibly be what was meant
+fn suggest_key<'a>(key: &str, allowed: &[&'a str]) -> Option<&'a str> {
+    // Short keys need to be closer to count as typos
+    let max_distance = if key.len() > 4 { 2 } else { 1 };
+    allowed
+        .iter()
+        .map(|allowed_key| (*allowed_key, edit_distance(key, allowed_key)))
+        .filter(|(_, distance)| *distance <= max_distance)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(allowed_key, _)| allowed_key)
+}
+
+// Gets the edit distance between two strings (the number of single-character insertions, deletions, substitutions, and adjacent transpositions to get from one to the other)
+// Transpositions are counted as one edit because they're such common typos (e.g. `exce`)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Keys are short, so we just build the whole matrix
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution_cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (distances[i - 1][j - 1] + substitution_cost)
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
@@ -0,0 +1,170 @@
+// This file contains the logic for config-level variables (the `[vars]` table), which can be interpolated into scripts as `%[vars.name]`
+// References between variables are resolved once while parsing, so they cost nothing when running from a cache
+// Environment variables (`%[env.NAME]`) are only resolved when a script is prepared, so the cache never holds their values (which may be secrets)
+
+use crate::schema;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Resolves the given raw variables, which can reference each other (`%[vars.name]`) and environment variables (`%[env.NAME]`)
+// References to environment variables are kept as they are, to be resolved when the variable is used (see `resolve_env_refs`)
+// This will return an error if a variable references another that doesn't exist or if variables reference each other in a cycle
+pub fn resolve_vars(raw_vars: &HashMap<String, String>) -> Result<HashMap<String, String>, String> {
+    // This works depth-first, keeping track of the variables we're in the middle of resolving to catch cycles
+    fn resolve_var(
+        name: &str,
+        raw_vars: &HashMap<String, String>,
+        resolved: &mut HashMap<String, String>,
+        stack: &mut Vec<String>,
+    ) -> Result<String, String> {
+        if let Some(value) = resolved.get(name) {
+            return Ok(value.to_string());
+        }
+        if let Some(idx) = stack.iter().position(|stack_name| stack_name == name) {
+            let mut cycle = stack[idx..].to_vec();
+            cycle.push(name.to_string());
+            return Err(format!("Error in parsing Bonnie configuration file: variables reference each other in a cycle ({}). Please break the cycle in your `[vars]` table.", cycle.join(" -> ")));
+        }
+        let raw_value = match raw_vars.get(name) {
+            Some(raw_value) => raw_value,
+            None => return Err(format!("Error in parsing Bonnie configuration file: the variable '{}' is referenced, but it isn't defined in your `[vars]` table.", name))
+        };
+
+        stack.push(name.to_string());
+        // We can't use `?` inside the closure for `replace_all`, so we keep the first error and return it afterwards
+        let mut err = None;
+        let value = var_regex().replace_all(raw_value, |captures: &Captures| {
+            let res = match &captures[1] {
+                "vars" => resolve_var(&captures[2], raw_vars, resolved, stack),
+                _ => Ok(captures[0].to_string()),
+            };
+            match res {
+                Ok(value) => value,
+                Err(var_err) => {
+                    err.get_or_insert(var_err);
+                    String::new()
+                }
+            }
+        });
+        if let Some(err) = err {
+            return Err(err);
+        }
+        let value = value.to_string();
+        stack.pop();
+        resolved.insert(name.to_string(), value.to_string());
+
+        Ok(value)
+    }
+
+    let mut resolved = HashMap::new();
+    for name in raw_vars.keys() {
+        resolve_var(name, raw_vars, &mut resolved, &mut Vec::new())?;
+    }
+
+    Ok(resolved)
+}
+
+// Interpolates the given resolved variables into every command, shell, and description in the given scripts
+// Any `%[env.NAME]` references this brings in are resolved later (by the interpolation engine for commands, and `resolve_env_refs` for everything else)
+pub fn interpolate_vars_into_scripts(
+    scripts: &mut schema::Scripts,
+    vars: &HashMap<String, String>,
+) -> Result<(), String> {
+    // Interpolates into a single command core (commands and shell)
+    fn interpolate_core(
+        core: &mut schema::CommandCore,
+        vars: &HashMap<String, String>,
+    ) -> Result<(), String> {
+        for cmd_str in core.exec.iter_mut() {
+            *cmd_str = interpolate_vars(cmd_str, vars)?;
+        }
+        if let Some(shell) = &mut core.shell {
+            for part in shell.parts.iter_mut() {
+                *part = interpolate_vars(part, vars)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    for command in scripts.values_mut() {
+        for cmd in command.cmd.iter_mut().chain(command.else_cmd.iter_mut()) {
+            interpolate_core(&mut cmd.generic, vars)?;
+            for core in cmd.targets.values_mut() {
+                interpolate_core(core, vars)?;
+            }
+        }
+        if let Some(description) = &command.description {
+            command.description = Some(interpolate_vars(description, vars)?);
+        }
+        if let Some(subcommands) = &mut command.subcommands {
+            interpolate_vars_into_scripts(subcommands, vars)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Interpolates the given resolved variables into a string
+// Only `%[vars.name]` is touched here, so `%[prev.*]` is left for Bones to interpolate at runtime
+fn interpolate_vars(text: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut err = None;
+    let interpolated = var_regex().replace_all(text, |captures: &Captures| {
+        // Environment variables are resolved when the script is prepared, so those are left alone here
+        if &captures[1] != "vars" {
+            return captures[0].to_string();
+        }
+        match vars.get(&captures[2]) {
+            Some(value) => value.to_string(),
+            None => {
+                err.get_or_insert(format!("Error in parsing Bonnie configuration file: the variable '{}' is referenced, but it isn't defined in your `[vars]` table.", &captures[2]));
+                String::new()
+            }
+        }
+    });
+
+    match err {
+        Some(err) => Err(err),
+        None => Ok(interpolated.to_string()),
+    }
+}
+
+// Resolves the `%[env.NAME]` references in the given text (which come from variables) with the given function for getting environment variables
+// Commands have these resolved by the interpolation engine, this is for shells and descriptions
+pub fn resolve_env_refs(
+    text: &str,
+    get_env: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut err = None;
+    let resolved = var_regex().replace_all(text, |captures: &Captures| {
+        if &captures[1] != "env" {
+            return captures[0].to_string();
+        }
+        match get_env(&captures[2]) {
+            Some(env_var) => env_var,
+            None => {
+                err.get_or_insert(env_ref_error(&captures[2]));
+                String::new()
+            }
+        }
+    });
+
+    match err {
+        Some(err) => Err(err),
+        None => Ok(resolved.to_string()),
+    }
+}
+
+// Gets the error for an environment variable referenced with `%[env.NAME]` that couldn't be loaded
+pub fn env_ref_error(env_var_name: &str) -> String {
+    format!("The environment variable '{name}' referenced with `%[env.{name}]` couldn't be loaded. This means it either hasn't been defined (you may need to load another environment variable file) or contains invalid characters.", name = env_var_name)
+}
+
+// Gets the regular expression that matches variable and environment variable references
+// This is used for every variable and every script, so it's only compiled once
+fn var_regex() -> &'static Regex {
+    static VAR_REGEX: OnceLock<Regex> = OnceLock::new();
+    // We can unwrap this because we know it's valid
+    VAR_REGEX.get_or_init(|| Regex::new(r"%\[(vars|env)\.([^\]]+)\]").unwrap())
+}
@@ -0,0 +1,214 @@
+// This file contains the logic to run a script across every member of a workspace (`bonnie --all <script>`)
+// Each member is a separate project with its own Bonnie configuration, so members are run by separate Bonnie processes in their own directories
+
+use crate::get_cfg::find_cfg_in_dir;
+use crate::raw_schema;
+use crate::schema;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command as OsCommand};
+
+// What happened when running a script in a workspace member
+enum MemberResult {
+    Ran(i32),
+    // The member's configuration doesn't define the script
+    Skipped,
+    // The member's Bonnie process couldn't be run at all
+    Failed(String),
+}
+
+// Runs the script given by the arguments in every member of the workspace in the given config, returning the aggregated exit code
+// This takes the path to the root config (members are relative to it) and the Bonnie executable to run members with
+// The aggregated exit code is that of the first member (in the order given) that didn't succeed, or 0 if they all did
+pub fn run_workspace(
+    cfg: &schema::Config,
+    cfg_path: &str,
+    args: &[String],
+    bonnie_exe: &Path,
+    output: &mut impl std::io::Write,
+) -> Result<i32, String> {
+    let workspace = match &cfg.workspace {
+        Some(workspace) => workspace,
+        None => return Err(String::from("Your Bonnie configuration doesn't define a workspace, so `--all` can't be used. Please add `workspace.members` to your Bonnie configuration.")),
+    };
+    let script_name = match args.first() {
+        Some(script_name) => script_name,
+        None => return Err(String::from("Please provide a command to run across the workspace. You can use `bonnie help` in any member to see the commands available there.")),
+    };
+    let members = get_members(&workspace.members, Path::new(cfg_path))?;
+
+    // Work out which members can actually run the script first, so notices about skipped members come before any output from the others
+    // Members find their configs in the same way Bonnie normally does, so they don't have to use the same format as the root
+    let mut runnable = Vec::new();
+    for member in members.iter() {
+        let member_cfg_path = find_cfg_in_dir(member);
+        match member_cfg_path {
+            Some(member_cfg_path) if member_has_script(&member_cfg_path, script_name)? => {
+                runnable.push((member, member_cfg_path))
+            }
+            _ => {
+                writeln!(
+                    output,
+                    "Skipping workspace member '{}', which doesn't define the '{}' script.",
+                    member.display(),
+                    script_name
+                )
+                .expect("Failed to write workspace notice.");
+            }
+        }
+    }
+
+    let mut results: Vec<(&PathBuf, MemberResult)> = Vec::new();
+    if workspace.parallel {
+        // Start everything before waiting on anything
+        let children: Vec<(&PathBuf, Result<Child, String>)> = runnable
+            .iter()
+            .map(|(member, member_cfg_path)| {
+                (
+                    *member,
+                    spawn_member(member, member_cfg_path, args, bonnie_exe),
+                )
+            })
+            .collect();
+        for (member, child) in children {
+            results.push((member, wait_on_member(member, child)));
+        }
+    } else {
+        for (member, member_cfg_path) in runnable {
+            let child = spawn_member(member, &member_cfg_path, args, bonnie_exe);
+            results.push((member, wait_on_member(member, child)));
+        }
+    }
+
+    // Print a summary of every member (in the order given, including skipped ones)
+    writeln!(output, "Workspace summary for '{}':", script_name)
+        .expect("Failed to write workspace summary.");
+    let mut exit_code = 0;
+    for member in members.iter() {
+        let result = results
+            .iter()
+            .find(|(ran_member, _)| *ran_member == member)
+            .map(|(_, result)| result)
+            .unwrap_or(&MemberResult::Skipped);
+        let summary = match result {
+            MemberResult::Ran(0) => String::from("succeeded"),
+            MemberResult::Ran(member_exit_code) => {
+                if exit_code == 0 {
+                    exit_code = *member_exit_code;
+                }
+                format!("failed with exit code {}", member_exit_code)
+            }
+            MemberResult::Skipped => String::from("skipped"),
+            MemberResult::Failed(err) => {
+                if exit_code == 0 {
+                    exit_code = 1;
+                }
+                format!("couldn't be run ({})", err)
+            }
+        };
+        writeln!(output, "    {}: {}", member.display(), summary)
+            .expect("Failed to write workspace summary.");
+    }
+
+    Ok(exit_code)
+}
+
+// Gets the directories of all the workspace members matching the given patterns (relative to the root config)
+// A pattern that names a single directory exactly must exist, but globs matching nothing are fine
+fn get_members(patterns: &[String], cfg_path: &Path) -> Result<Vec<PathBuf>, String> {
+    let cfg_dir = cfg_path.parent().unwrap_or_else(|| Path::new(""));
+    let mut members = Vec::new();
+    for pattern in patterns.iter() {
+        let full_pattern = cfg_dir.join(pattern).to_string_lossy().to_string();
+        let paths = match glob::glob(&full_pattern) {
+            Ok(paths) => paths,
+            Err(err) => return Err(format!("Error in parsing Bonnie configuration file: the workspace member '{}' is not a valid glob pattern ({}).", pattern, err))
+        };
+        let mut paths_found = false;
+        for path in paths {
+            let path = match path {
+                Ok(path) => path,
+                Err(err) => {
+                    return Err(format!(
+                        "The following error occurred while searching for workspace members: '{}'.",
+                        err
+                    ))
+                }
+            };
+            // Globs like `services/*` will match loose files too, which can't be members
+            if path.is_dir() && !members.contains(&path) {
+                paths_found = true;
+                members.push(path);
+            }
+        }
+        if !paths_found && !pattern.contains(&['*', '?', '['][..]) {
+            return Err(format!("Error in parsing Bonnie configuration file: the workspace member directory '{}' doesn't exist.", full_pattern));
+        }
+    }
+
+    Ok(members)
+}
+
+// Checks whether the member config at the given path defines the given script (including through included files)
+fn member_has_script(member_cfg_path: &Path, script_name: &str) -> Result<bool, String> {
+    let cfg_string = match fs::read_to_string(member_cfg_path) {
+        Ok(cfg_string) => cfg_string,
+        Err(_) => return Err(format!("Error reading Bonnie configuration file at '{}', make sure you have the permissions to read it.", member_cfg_path.display()))
+    };
+    let member_cfg_path_str = member_cfg_path.to_string_lossy();
+    let format = raw_schema::ConfigFormat::detect(&member_cfg_path_str, &cfg_string);
+    let member_cfg = raw_schema::Config::new_with_format(&cfg_string, format)?
+        .load_includes(&member_cfg_path_str)
+        .map_err(|err| {
+            format!(
+                "In workspace member '{}': {}",
+                member_cfg_path.display(),
+                err
+            )
+        })?;
+
+    Ok(member_cfg.has_script(script_name))
+}
+
+// Starts a Bonnie process running the given arguments in the given member directory with the given config
+fn spawn_member(
+    member: &Path,
+    member_cfg_path: &Path,
+    args: &[String],
+    bonnie_exe: &Path,
+) -> Result<Child, String> {
+    let child = OsCommand::new(bonnie_exe)
+        .args(args)
+        .current_dir(member)
+        // The member should use its own config and cache, not whatever was set for the root
+        // The path to the config is absolute or relative to where we are, so we give the member just the file name
+        .env(
+            "BONNIE_CONF",
+            match member_cfg_path.file_name() {
+                Some(file_name) => Path::new(".").join(file_name),
+                None => member_cfg_path.to_path_buf(),
+            },
+        )
+        .env_remove("BONNIE_CACHE")
+        .spawn();
+    match child {
+        Ok(child) => Ok(child),
+        Err(err) => Err(format!("Bonnie couldn't be started: '{}'", err)),
+    }
+}
+
+// Waits on a member's Bonnie process to finish
+fn wait_on_member(member: &Path, child: Result<Child, String>) -> MemberResult {
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => return MemberResult::Failed(err),
+    };
+    match child.wait() {
+        // Bonnie itself always exits with a code, but it could still be killed by a signal
+        Ok(exit_status) => MemberResult::Ran(exit_status.code().unwrap_or(1)),
+        Err(_) => MemberResult::Failed(format!(
+            "parent unable to wait on the Bonnie process for '{}'",
+            member.display()
+        )),
+    }
+}
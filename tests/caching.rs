@@ -1,5 +1,8 @@
 use lib::{cache, load_from_cache, Config, FinalConfig, BONNIE_VERSION};
+#[cfg(feature = "binary-cache")]
+use lib::{write_cache_as, CacheFormat};
 use std::env;
+use std::fs;
 
 // Each test in this file MUST have a separate temporary file to write to, otherwise undefined conflicts occur!
 
@@ -52,7 +55,7 @@ fn cache_works() {
     let res = cache(&cfg, &mut output, Some(&tmp_path));
     assert_eq!(res, Ok(()));
     let cfg_extracted = load_from_cache(&mut output, Some(&tmp_path));
-    assert_eq!(cfg_extracted, Ok(cfg));
+    assert_eq!(cfg_extracted, Ok(Some(cfg)));
 }
 // That config loads `.env`, so we should be able to access `SHORTGREETING`
 #[test]
@@ -74,5 +77,145 @@ fn returns_error_on_bad_version() {
     cfg.version = "0.1.0".to_string(); // No matter what, this version is incompatible (we're past it now)
     cache(&cfg, &mut output, Some(&tmp_path)).unwrap();
     let cfg_extracted = load_from_cache(&mut output, Some(&tmp_path));
-    assert!(matches!(cfg_extracted, Err(_)));
+    assert!(cfg_extracted.is_err());
+}
+// If a file the config was built from changes, the cache should be reported as stale so it can be rebuilt
+#[test]
+fn detects_stale_cache() {
+    let tmp_path = "/tmp/bonnie_test_3.cache.json".to_string();
+    let env_path = "/tmp/bonnie_test_3.env";
+    fs::write(env_path, "STALE_CACHE_TEST=old").unwrap();
+    let cfg_str = format!(
+        "version = \"{}\"\nenv_files = [\"{}\"]\n[scripts]\nbasic = \"exit 0\"",
+        BONNIE_VERSION, env_path
+    );
+    let cfg = Config::new(&cfg_str)
+        .unwrap()
+        .to_final(BONNIE_VERSION, &mut Vec::new())
+        .unwrap();
+    let mut output = Vec::new();
+    cache(&cfg, &mut output, Some(&tmp_path)).unwrap();
+    // Rewriting the file with the same contents shouldn't invalidate anything
+    fs::write(env_path, "STALE_CACHE_TEST=old").unwrap();
+    let cfg_extracted = load_from_cache(&mut output, Some(&tmp_path));
+    assert_eq!(cfg_extracted, Ok(Some(cfg)));
+    // But actually changing it should
+    fs::write(env_path, "STALE_CACHE_TEST=new").unwrap();
+    let cfg_extracted = load_from_cache(&mut output, Some(&tmp_path));
+    assert_eq!(cfg_extracted, Ok(None));
+}
+// Caches written by older versions of Bonnie can't be read, so they should just be rebuilt
+#[test]
+fn treats_old_cache_format_as_stale() {
+    let tmp_path = "/tmp/bonnie_test_4.cache.json".to_string();
+    let cfg = get_cfg(BONNIE_VERSION);
+    fs::write(&tmp_path, serde_json::to_string(&cfg).unwrap()).unwrap();
+    let cfg_extracted = load_from_cache(&mut Vec::new(), Some(&tmp_path));
+    assert_eq!(cfg_extracted, Ok(None));
+}
+#[cfg(feature = "binary-cache")]
+#[test]
+fn binary_cache_works() {
+    let tmp_path = "/tmp/bonnie_test_5.cache.bin".to_string();
+    let cfg = get_cfg(BONNIE_VERSION);
+    write_cache_as(&cfg, Some(&tmp_path), CacheFormat::Binary).unwrap();
+    let cfg_extracted = load_from_cache(&mut Vec::new(), Some(&tmp_path));
+    assert_eq!(cfg_extracted, Ok(Some(cfg)));
+}
+// Binary caches aren't self-describing, so one with a different format version has to be rebuilt
+#[cfg(feature = "binary-cache")]
+#[test]
+fn treats_binary_cache_with_other_format_version_as_stale() {
+    let tmp_path = "/tmp/bonnie_test_6.cache.bin".to_string();
+    let cfg = get_cfg(BONNIE_VERSION);
+    write_cache_as(&cfg, Some(&tmp_path), CacheFormat::Binary).unwrap();
+    let mut cache_bytes = fs::read(&tmp_path).unwrap();
+    cache_bytes[8] = cache_bytes[8].wrapping_add(1); // The format version comes straight after the 8 magic bytes
+    fs::write(&tmp_path, cache_bytes).unwrap();
+    let cfg_extracted = load_from_cache(&mut Vec::new(), Some(&tmp_path));
+    assert_eq!(cfg_extracted, Ok(None));
+}
+// The layout can change between versions of Bonnie even if the format version doesn't, so caches from other versions have to be rebuilt too
+#[cfg(feature = "binary-cache")]
+#[test]
+fn treats_binary_cache_from_other_bonnie_version_as_stale() {
+    let tmp_path = "/tmp/bonnie_test_9.cache.bin".to_string();
+    let cfg = get_cfg(BONNIE_VERSION);
+    write_cache_as(&cfg, Some(&tmp_path), CacheFormat::Binary).unwrap();
+    let mut cache_bytes = fs::read(&tmp_path).unwrap();
+    // The version of Bonnie comes after the magic bytes and the format version
+    let version_end = 12 + BONNIE_VERSION.len();
+    assert_eq!(&cache_bytes[12..version_end], BONNIE_VERSION.as_bytes());
+    cache_bytes[12] = cache_bytes[12].wrapping_add(1);
+    fs::write(&tmp_path, cache_bytes).unwrap();
+    let cfg_extracted = load_from_cache(&mut Vec::new(), Some(&tmp_path));
+    assert_eq!(cfg_extracted, Ok(None));
+}
+// Environment variables referenced by `[vars]` have to be resolved when scripts are run, otherwise the cache would keep their old values (and write them to disk)
+#[test]
+fn resolves_env_vars_in_vars_after_loading() {
+    let tmp_path = "/tmp/bonnie_test_7.cache.json".to_string();
+    let cfg_str = format!(
+        "version = \"{}\"\n[vars]\ntag = \"%[env.BONNIE_TEST_VARS_TAG]\"\n[scripts]\nshow = \"echo %[vars.tag]\"",
+        BONNIE_VERSION
+    );
+    env::set_var("BONNIE_TEST_VARS_TAG", "one");
+    let cfg = Config::new(&cfg_str)
+        .unwrap()
+        .to_final(BONNIE_VERSION, &mut Vec::new())
+        .unwrap();
+    let mut output = Vec::new();
+    cache(&cfg, &mut output, Some(&tmp_path)).unwrap();
+    assert!(!fs::read_to_string(&tmp_path).unwrap().contains("echo one"));
+
+    env::set_var("BONNIE_TEST_VARS_TAG", "two");
+    let cfg = load_from_cache(&mut output, Some(&tmp_path))
+        .unwrap()
+        .unwrap();
+    let (command, command_name, args) = cfg.get_command_for_args(&["show".to_string()]).unwrap();
+    let mut output = Vec::new();
+    command
+        .prepare(&command_name, &args, &cfg, &mut output)
+        .unwrap()
+        .dry_run(&command_name, &mut output)
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("echo two"));
+}
+// Files that start matching an include pattern after the cache was made aren't in its sources, so the patterns themselves have to be checked
+#[test]
+fn detects_new_included_files() {
+    let tmp_path = "/tmp/bonnie_test_8.cache.json".to_string();
+    let dir = "/tmp/bonnie_test_8";
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(format!("{}/scripts", dir)).unwrap();
+    fs::write(
+        format!("{}/scripts/old.toml", dir),
+        "[scripts]\nold = \"exit 0\"",
+    )
+    .unwrap();
+    let cfg_path = format!("{}/bonnie.toml", dir);
+    let cfg_str = format!(
+        "version = \"{}\"\ninclude = [\"scripts/*.toml\"]\n[scripts]\nbasic = \"exit 0\"",
+        BONNIE_VERSION
+    );
+    fs::write(&cfg_path, &cfg_str).unwrap();
+    let cfg = Config::new(&cfg_str)
+        .unwrap()
+        .load_includes(&cfg_path)
+        .unwrap()
+        .to_final(BONNIE_VERSION, &mut Vec::new())
+        .unwrap();
+    let mut output = Vec::new();
+    cache(&cfg, &mut output, Some(&tmp_path)).unwrap();
+    let cfg_extracted = load_from_cache(&mut output, Some(&tmp_path));
+    assert_eq!(cfg_extracted, Ok(Some(cfg)));
+
+    fs::write(
+        format!("{}/scripts/new.toml", dir),
+        "[scripts]\nnew = \"exit 0\"",
+    )
+    .unwrap();
+    let cfg_extracted = load_from_cache(&mut output, Some(&tmp_path));
+    assert_eq!(cfg_extracted, Ok(None));
 }
@@ -0,0 +1,125 @@
+// These tests check that configs in every supported format are parsed into the same thing
+// Nothing is actually run here, so these aren't OS-specific
+
+use lib::{Config, ConfigFormat, FinalConfig, BONNIE_VERSION};
+
+const TOML_CFG: &str = r#"
+[scripts]
+basic = "exit 0"
+nested.subcommands.test.cmd = "exit 1"
+nested.subcommands.test.desc = "a test"
+nested.subcommands.other = "exit 2"
+nested.order = """
+test {
+    Any => other
+}
+"""
+"#;
+const YAML_CFG: &str = r#"
+scripts:
+  basic: exit 0
+  nested:
+    subcommands:
+      test:
+        cmd: exit 1
+        desc: a test
+      other: exit 2
+    order: |
+      test {
+          Any => other
+      }
+"#;
+const JSON_CFG: &str = r#"{
+    "scripts": {
+        "basic": "exit 0",
+        "nested": {
+            "subcommands": {
+                "test": { "cmd": "exit 1", "desc": "a test" },
+                "other": "exit 2"
+            },
+            "order": "test {\n    Any => other\n}"
+        }
+    }
+}"#;
+
+// A testing utility that fully parses the given config in the given format
+#[cfg(test)]
+fn parse(cfg_str: &str, format: Option<ConfigFormat>) -> Result<FinalConfig, String> {
+    let raw_cfg = match format {
+        Some(format) => Config::new_with_format(cfg_str, format)?,
+        None => Config::new(cfg_str)?,
+    };
+    raw_cfg.to_final(BONNIE_VERSION, &mut Vec::new())
+}
+
+#[test]
+fn parses_all_formats_identically() {
+    let toml_cfg = parse(
+        &(format!("version = \"{}\"", BONNIE_VERSION) + TOML_CFG),
+        Some(ConfigFormat::Toml),
+    )
+    .unwrap();
+    let yaml_cfg = parse(
+        &(format!("version: \"{}\"", BONNIE_VERSION) + YAML_CFG),
+        Some(ConfigFormat::Yaml),
+    )
+    .unwrap();
+    let json_cfg = parse(
+        &JSON_CFG.replacen('{', &format!("{{\n\"version\": \"{}\",", BONNIE_VERSION), 1),
+        Some(ConfigFormat::Json),
+    )
+    .unwrap();
+    assert_eq!(toml_cfg, yaml_cfg);
+    assert_eq!(toml_cfg, json_cfg);
+}
+#[test]
+fn sniffs_formats_from_contents() {
+    assert_eq!(
+        ConfigFormat::sniff("# comment\nversion = \"0.3.2\""),
+        ConfigFormat::Toml
+    );
+    assert_eq!(
+        ConfigFormat::sniff("[scripts]\nbasic = \"exit 0\""),
+        ConfigFormat::Toml
+    );
+    assert_eq!(
+        ConfigFormat::sniff("version: \"0.3.2\""),
+        ConfigFormat::Yaml
+    );
+    assert_eq!(
+        ConfigFormat::sniff("scripts:\n  basic: a=b"),
+        ConfigFormat::Yaml
+    );
+    assert_eq!(
+        ConfigFormat::sniff("{\"version\": \"0.3.2\"}"),
+        ConfigFormat::Json
+    );
+    // Sniffing is used without the right extension
+    assert_eq!(
+        ConfigFormat::detect("./bonnie.conf", "version: \"0.3.2\""),
+        ConfigFormat::Yaml
+    );
+}
+#[test]
+fn detects_formats_from_extensions() {
+    assert_eq!(
+        ConfigFormat::detect("./bonnie.toml", ""),
+        ConfigFormat::Toml
+    );
+    assert_eq!(
+        ConfigFormat::detect("./bonnie.yaml", ""),
+        ConfigFormat::Yaml
+    );
+    assert_eq!(ConfigFormat::detect("./bonnie.yml", ""), ConfigFormat::Yaml);
+    assert_eq!(
+        ConfigFormat::detect("./bonnie.json", ""),
+        ConfigFormat::Json
+    );
+}
+#[test]
+fn returns_format_specific_errors() {
+    let err = parse("scripts: [", Some(ConfigFormat::Yaml)).unwrap_err();
+    assert!(err.starts_with("Invalid Bonnie configuration file (YAML)."));
+    let err = parse("{\"scripts\": {}}", Some(ConfigFormat::Json)).unwrap_err();
+    assert!(err.contains("\"version\": \""));
+}
@@ -0,0 +1,118 @@
+// These tests check the diagrams generated for ordered subcommands
+// Nothing is actually run here, so these aren't OS-specific
+
+use lib::{Config, GraphFormat, BONNIE_VERSION};
+
+const CFG_STR: &str = r#"
+[scripts]
+basic.subcommands.test.cmd = "exit 0"
+basic.subcommands.test.desc = "runs the \"tests\""
+basic.subcommands.other = "exit 1"
+basic.subcommands.nested.subcommands.test = "exit 2"
+basic.subcommands.nested.subcommands.other = "exit 3"
+basic.subcommands.nested.order = """
+test {
+    Any => other
+}
+"""
+basic.order = """
+test {
+    Success => nested,
+    1|2 => other
+}
+"""
+unordered.subcommands.test = "exit 0"
+cleaned.subcommands.test = "exit 0"
+cleaned.subcommands.other = "exit 1"
+cleaned.subcommands.teardown = "exit 0"
+cleaned.order = """
+test {
+    Success => other
+}
+"""
+cleaned.finally = ["teardown", "unordered.test"]
+"#;
+
+// A testing utility that graphs the command given by the arguments in the test config
+#[cfg(test)]
+fn graph(args: Vec<&str>, format: GraphFormat) -> Result<String, String> {
+    let cfg_str = "version = \"".to_string() + BONNIE_VERSION + "\"\n" + CFG_STR;
+    let cfg = Config::new(&cfg_str)
+        .unwrap()
+        .to_final(BONNIE_VERSION, &mut Vec::new())
+        .unwrap();
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    cfg.graph(&args, format)
+}
+
+#[test]
+fn graphs_as_dot() {
+    let graph = graph(vec!["basic"], GraphFormat::Dot).unwrap();
+    assert_eq!(
+        graph,
+        r#"digraph "basic" {
+    node [shape=box];
+    n0 [label="test\nruns the \"tests\""];
+    n1 [label="other"];
+    n2 [label="nested"];
+    subgraph cluster_n2 {
+        label="nested";
+        n3 [label="test"];
+        n4 [label="other"];
+        n3 -> n4 [label="Any"];
+    }
+    n2 -> n3 [style=dashed];
+    n0 -> n1 [label="1|2"];
+    n0 -> n2 [label="Success"];
+}"#
+    );
+}
+#[test]
+fn graphs_as_mermaid() {
+    let graph = graph(vec!["basic"], GraphFormat::Mermaid).unwrap();
+    assert_eq!(
+        graph,
+        r#"flowchart TD
+    n0["test<br/>runs the #quot;tests#quot;"]
+    n1["other"]
+    n2["nested"]
+    subgraph n2_steps ["nested"]
+        n3["test"]
+        n4["other"]
+        n3 -->|"Any"| n4
+    end
+    n2 -.-> n3
+    n0 -->|"1|2"| n1
+    n0 -->|"Success"| n2"#
+    );
+}
+#[test]
+fn graphs_cleanup_steps() {
+    let dot = graph(vec!["cleaned"], GraphFormat::Dot).unwrap();
+    assert_eq!(
+        dot,
+        r#"digraph "cleaned" {
+    node [shape=box];
+    n0 [label="test"];
+    n1 [label="other"];
+    n0 -> n1 [label="Success"];
+    n2 [label="teardown, unordered.test"];
+    n1 -> n2 [label="finally", style=dotted];
+}"#
+    );
+    let mermaid = graph(vec!["cleaned"], GraphFormat::Mermaid).unwrap();
+    assert_eq!(
+        mermaid,
+        r#"flowchart TD
+    n0["test"]
+    n1["other"]
+    n0 -->|"Success"| n1
+    n2["teardown, unordered.test"]
+    n1 -.->|"finally"| n2"#
+    );
+}
+#[test]
+fn returns_error_on_unordered_command() {
+    let graph = graph(vec!["unordered", "test"], GraphFormat::Dot);
+    assert!(graph.is_err());
+}
@@ -0,0 +1,215 @@
+use lib::{Config, FinalConfig, BONNIE_VERSION};
+use std::fs;
+
+// Each test in this file MUST have a separate temporary directory to write to, otherwise undefined conflicts occur!
+
+// A testing utility that writes the given files (paths relative to the given directory) and parses the config at `bonnie.toml` in it
+// The main config given here does not have to contain any version tag, that will be added
+#[cfg(test)]
+fn get_cfg(dir: &str, main_cfg: &str, files: Vec<(&str, &str)>) -> Result<FinalConfig, String> {
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir).unwrap();
+    for (path, contents) in files {
+        let path = format!("{}/{}", dir, path);
+        fs::create_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    let cfg_path = format!("{}/bonnie.toml", dir);
+    let cfg_str = "version = \"".to_string() + BONNIE_VERSION + "\"\n" + main_cfg;
+    fs::write(&cfg_path, &cfg_str).unwrap();
+    Config::new(&cfg_str)?
+        .load_includes(&cfg_path)?
+        .to_final(BONNIE_VERSION, &mut Vec::new())
+}
+
+#[test]
+fn merges_included_scripts() {
+    let cfg = get_cfg(
+        "/tmp/bonnie_test_include_0",
+        r#"
+        include = ["ci/bonnie.toml", "scripts/*.toml"]
+        [scripts]
+        basic = "exit 0"
+        "#,
+        vec![
+            ("ci/bonnie.toml", "[scripts]\nci = \"exit 1\""),
+            ("scripts/a.toml", "[scripts]\na = \"exit 2\""),
+            (
+                "scripts/b.toml",
+                "include = [\"../nested.toml\"]\n[scripts]\nb.cmd = \"exit 3\"\nb.deps = [\"a\"]",
+            ),
+            ("nested.toml", "[scripts]\nnested = \"exit 4\""),
+        ],
+    )
+    .unwrap();
+    let mut script_names: Vec<&String> = cfg.scripts.keys().collect();
+    script_names.sort();
+    assert_eq!(script_names, vec!["a", "b", "basic", "ci", "nested"]);
+}
+#[test]
+fn returns_error_naming_both_files_on_conflict() {
+    let err = get_cfg(
+        "/tmp/bonnie_test_include_1",
+        r#"
+        include = ["other.toml"]
+        [scripts]
+        basic = "exit 0"
+        "#,
+        vec![("other.toml", "[scripts]\nbasic = \"exit 1\"")],
+    )
+    .unwrap_err();
+    assert!(err.contains("/tmp/bonnie_test_include_1/bonnie.toml"));
+    assert!(err.contains("/tmp/bonnie_test_include_1/other.toml"));
+}
+#[test]
+fn returns_error_on_missing_included_file() {
+    let res = get_cfg(
+        "/tmp/bonnie_test_include_2",
+        r#"
+        include = ["nonexistent.toml", "nonexistent/*.toml"]
+        [scripts]
+        basic = "exit 0"
+        "#,
+        vec![],
+    );
+    assert!(res.is_err());
+}
+#[test]
+fn resolves_included_env_files_relative_to_included_file() {
+    let cfg = get_cfg(
+        "/tmp/bonnie_test_include_3",
+        r#"
+        include = ["ci/bonnie.toml"]
+        [scripts]
+        basic = "exit 0"
+        "#,
+        vec![
+            (
+                "ci/bonnie.toml",
+                "env_files = [\".env\"]\n[scripts]\nci = \"exit 0\"",
+            ),
+            ("ci/.env", "BONNIE_TEST_INCLUDED_VAR=included"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        cfg.env_files,
+        vec!["/tmp/bonnie_test_include_3/ci/.env".to_string()]
+    );
+    assert_eq!(
+        std::env::var("BONNIE_TEST_INCLUDED_VAR"),
+        Ok("included".to_string())
+    );
+}
+#[test]
+fn ignores_include_cycles() {
+    let cfg = get_cfg(
+        "/tmp/bonnie_test_include_4",
+        r#"
+        include = ["other.toml"]
+        [scripts]
+        basic = "exit 0"
+        "#,
+        vec![(
+            "other.toml",
+            "include = [\"bonnie.toml\"]\n[scripts]\nother = \"exit 0\"",
+        )],
+    )
+    .unwrap();
+    assert_eq!(cfg.scripts.len(), 2);
+}
+#[test]
+fn resolves_included_script_env_files_relative_to_included_file() {
+    let cfg = get_cfg(
+        "/tmp/bonnie_test_include_5",
+        r#"
+        include = ["ci/bonnie.toml"]
+        [scripts]
+        basic = "exit 0"
+        "#,
+        vec![(
+            "ci/bonnie.toml",
+            "[scripts]\nci.cmd = \"exit 0\"\nci.env_files = [\".env\", \"?.env.local\"]\nci.subcommands.inner = \"exit 0\"",
+        )],
+    )
+    .unwrap();
+    let expected_env_files = vec![
+        "/tmp/bonnie_test_include_5/ci/.env".to_string(),
+        "?/tmp/bonnie_test_include_5/ci/.env.local".to_string(),
+    ];
+    assert_eq!(cfg.scripts["ci"].env_files, expected_env_files);
+    // Subcommands use their parents' files too
+    assert_eq!(
+        cfg.scripts["ci"].subcommands.as_ref().unwrap()["inner"].env_files,
+        expected_env_files
+    );
+}
+#[test]
+fn resolves_included_conditions_relative_to_included_file() {
+    let cfg = get_cfg(
+        "/tmp/bonnie_test_include_8",
+        r#"
+        include = ["ci/bonnie.toml"]
+        [scripts]
+        basic = "exit 0"
+        "#,
+        vec![
+            (
+                "ci/bonnie.toml",
+                "[scripts]\nci.cmd = \"exit 0\"\nci.when = { file_exists = \"marker\" }\nci.else = \"exit 1\"",
+            ),
+            ("ci/marker", ""),
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        cfg.scripts["ci"].when.as_ref().unwrap().file_exists,
+        Some("/tmp/bonnie_test_include_8/ci/marker".to_string())
+    );
+}
+#[test]
+fn reports_unknown_keys_in_included_files() {
+    let err = get_cfg(
+        "/tmp/bonnie_test_include_6",
+        r#"
+        include = ["ci/bonnie.toml"]
+        strict = true
+        [scripts]
+        basic = "exit 0"
+        "#,
+        vec![(
+            "ci/bonnie.toml",
+            "default_shell = \"bash\"\n[scripts]\nci.cmd = \"exit 1\"\nci.descr = \"typo\"",
+        )],
+    )
+    .unwrap_err();
+    assert!(err.contains(
+        "unknown key 'scripts.ci.descr' (did you mean 'scripts.ci.desc'?) in the included file '/tmp/bonnie_test_include_6/ci/bonnie.toml'"
+    ));
+    // Included files can't set anything that belongs to the main configuration file
+    assert!(err.contains(
+        "unknown key 'default_shell' in the included file '/tmp/bonnie_test_include_6/ci/bonnie.toml'"
+    ));
+}
+#[test]
+fn points_errors_in_included_scripts_at_included_file() {
+    let err = get_cfg(
+        "/tmp/bonnie_test_include_7",
+        r#"
+        include = ["ci/bonnie.toml"]
+        [scripts]
+        basic = "exit 0"
+        "#,
+        vec![(
+            "ci/bonnie.toml",
+            "[scripts]\nci.cmd = \"exit 0\"\nci.finally = [\"basic\"]",
+        )],
+    )
+    .unwrap_err();
+    assert!(err.ends_with(
+        " --> /tmp/bonnie_test_include_7/ci/bonnie.toml:3:4
+  |
+3 | ci.finally = [\"basic\"]
+  |    ^^^^^^^"
+    ));
+}
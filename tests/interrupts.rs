@@ -0,0 +1,64 @@
+// These tests run the actual Bonnie executable, because interrupts are only handled by it (not by the library)
+// Each test in this file MUST have a separate temporary directory to write to, otherwise undefined conflicts occur!
+
+use lib::BONNIE_VERSION;
+use std::fs;
+use std::process::Command;
+
+// A testing utility that writes the given config to the given directory and runs Bonnie there with the given arguments
+// This returns the exit code of Bonnie and everything it printed
+#[cfg(test)]
+fn run_in_dir(dir: &str, cfg: &str, args: Vec<&str>) -> (i32, String) {
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir).unwrap();
+    fs::write(
+        format!("{}/bonnie.toml", dir),
+        "version = \"".to_string() + BONNIE_VERSION + "\"\n" + cfg,
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_bonnie"))
+        .args(args)
+        .current_dir(dir)
+        .env_remove("BONNIE_CONF")
+        .env_remove("BONNIE_CACHE")
+        .output()
+        .unwrap();
+
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn runs_every_step_of_ordered_cleanup_after_interrupt() {
+    // The first step interrupts Bonnie itself (its parent), then waits so the interrupt is definitely handled before it finishes
+    let (exit_code, output) = run_in_dir(
+        "/tmp/bonnie_test_interrupts_0",
+        r#"
+[scripts]
+basic.subcommands.test = "kill -INT $PPID && sleep 1"
+basic.subcommands.other = "echo other"
+basic.subcommands.teardown.subcommands.first = "echo first teardown"
+basic.subcommands.teardown.subcommands.second = "echo second teardown"
+basic.subcommands.teardown.order = """
+first {
+    Any => second
+}
+"""
+basic.order = """
+test {
+    Any => other
+}
+"""
+basic.finally = ["teardown"]
+"#,
+        vec!["basic"],
+    );
+    assert_eq!(exit_code, 0);
+    // Nothing else should be started after the interrupt, except cleanup, which should run in full
+    assert!(!output.contains("\nother\n"));
+    assert!(output.contains("\nfirst teardown\n"));
+    assert!(output.contains("\nsecond teardown\n"));
+}
@@ -20,7 +20,7 @@ fn run_e2e_test(
 ) -> Result<i32, String> {
     let cfg = Config::new(cfg_str)?.to_final(version, output)?;
     let (command_to_run, command_name, relevant_args) = cfg.get_command_for_args(&prog_args)?;
-    let bone = command_to_run.prepare(&command_name, &relevant_args, &cfg.default_shell)?;
+    let bone = command_to_run.prepare(&command_name, &relevant_args, &cfg, output)?;
     // We don't want it verbose, it'll be so anyway in development
     let exit_code = bone.run(&command_name, false, output)?;
 
@@ -44,7 +44,7 @@ macro_rules! expect_exit_code {
             let output_string = String::from_utf8(output).unwrap();
             let output_lines: Vec<String> = output_string.lines().map(|x| x.to_string()).collect();
             output_lines
-        };
+        }
     }
 }
 
@@ -62,7 +62,7 @@ macro_rules! expect_error {
             let cfg_str = "version = \"".to_string() + $version + "\"\n" + $raw_cfg_str;
             let res = run_e2e_test(&cfg_str, prog_args, $version, &mut output);
             println!("{:#?}", res);
-            assert!(matches!(res, Err(_)));
+            assert!(res.is_err());
             // We know this will only be filled with `u8` bytes, so we can safely call `.unwrap()`
             let output_string = String::from_utf8(output).unwrap();
             let output_lines: Vec<String> = output_string.lines().map(|x| x.to_string()).collect();
@@ -298,9 +298,30 @@ fn succeeds_with_full_interpolation() {
         BONNIE_VERSION,
         ["basic", "Name", "(extra stuff)"]
     );
+    // Arguments are quoted even inside the command's own quotes, so those quotes end up in the output (see the changelog)
+    assert_contains_ordered!(
+        output,
+        ["sh, [\"-c\", \"echo \\\"Hello Name '(extra stuff)'\\\" && exit 0\"]"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_full_interpolation_unquoted() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        env_files = ["src/.env"]
+        [scripts]
+        basic.cmd = "echo %SHORTGREETING %name %% && exit 0"
+        basic.args = ["name"]
+        basic.env_vars = ["SHORTGREETING"]
+        "#,
+        BONNIE_VERSION,
+        ["basic", "Name", "(extra stuff)"]
+    );
     assert_contains_ordered!(
         output,
-        ["sh, [\"-c\", \"echo \\\"Hello Name (extra stuff)\\\" && exit 0\"]"]
+        ["sh, [\"-c\", \"echo Hello Name '(extra stuff)' && exit 0\"]"]
     );
 }
 #[test]
@@ -370,7 +391,7 @@ fn succeeds_with_multistage_and_interpolation_unordered_subcommands() {
     let output1 = expect_exit_code!(1, cfg, BONNIE_VERSION, ["basic", "test", "Name", "foo bar"]);
     assert_contains_ordered!(
         output1,
-        ["sh, [\"-c\", \"echo Hello foo bar && echo Name && exit 1\"]"]
+        ["sh, [\"-c\", \"echo Hello 'foo bar' && echo Name && exit 1\"]"]
     );
     let output2 = expect_exit_code!(1, cfg, BONNIE_VERSION, ["basic", "other"]);
     assert_contains_ordered!(output2, ["sh, [\"-c\", \"exit 1\"]"]);
@@ -929,3 +950,1199 @@ fn succeeds_with_intersection_order_control() {
         ["sh, [\"-c\", \"exit 0\"]", "sh, [\"-c\", \"exit 1\"]"]
     );
 }
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_top_level_script_references_in_order() {
+    let output = expect_exit_code!(
+        3,
+        r#"
+        [scripts]
+        build = "exit 0"
+        backend.subcommands.build = "exit 3"
+        basic.subcommands.test = "exit 1"
+        basic.order = """
+        build {
+            Success => backend.build
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains_ordered!(
+        output,
+        ["sh, [\"-c\", \"exit 0\"]", "sh, [\"-c\", \"exit 3\"]"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn passes_args_to_script_references_in_order() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        deploy.cmd = "echo deploying to %env"
+        deploy.args = ["env"]
+        ci.subcommands.test = "exit 0"
+        ci.order = """
+        test {
+            Success => deploy
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["ci", "prod"]
+    );
+    assert_contains_ordered!(
+        output,
+        [
+            "sh, [\"-c\", \"exit 0\"]",
+            "sh, [\"-c\", \"echo deploying to prod\"]"
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn returns_error_on_unknown_script_reference_in_order() {
+    expect_error!(
+        r#"
+        [scripts]
+        basic.subcommands.test = "exit 0"
+        basic.order = """
+        test {
+            Any => backend.build
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn returns_error_on_script_reference_cycle_in_order() {
+    expect_error!(
+        r#"
+        [scripts]
+        first.subcommands.test = "exit 0"
+        first.order = """
+        test {
+            Any => second
+        }
+        """
+        second.subcommands.test = "exit 0"
+        second.order = """
+        test {
+            Any => first
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["first"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_previous_step_interpolation_in_order() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.subcommands.test = "exit 3"
+        basic.subcommands.report = "echo %[prev.name] failed with %[prev.exit_code] && exit 0"
+        basic.order = """
+        test {
+            Failure => report
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains_ordered!(
+        output,
+        [
+            "sh, [\"-c\", \"exit 3\"]",
+            "sh, [\"-c\", \"echo test failed with 3 && exit 0\"]"
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_previous_step_interpolation_outside_order() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic = "echo \"%[prev.name]\" && exit 0"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains_ordered!(output, ["sh, [\"-c\", \"echo \\\"\\\" && exit 0\"]"]);
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_previous_step_placeholders_in_args_and_escapes() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.args = ["name"]
+        basic.subcommands.test = "exit 0"
+        basic.subcommands.report = "echo got %name \\%[prev.name] after %[prev.name] && exit 0"
+        basic.order = """
+        test {
+            Success => report
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["basic", "%[prev.name]"]
+    );
+    assert_contains_ordered!(
+        output,
+        ["sh, [\"-c\", \"echo got '%[prev.name]' %[prev.name] after test && exit 0\"]"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_signal_order_control() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.subcommands.test = "kill -9 $$"
+        basic.subcommands.killed = "exit 0"
+        basic.subcommands.other = "exit 1"
+        basic.order = """
+        test {
+            Signal(9) => killed,
+            1|Success => other
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains_ordered!(
+        output,
+        ["sh, [\"-c\", \"kill -9 $$\"]", "sh, [\"-c\", \"exit 0\"]"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_signal_order_control_for_children_of_shell() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.subcommands.test.cmd = ["echo start", "sh -c 'kill -9 $$'"]
+        basic.subcommands.killed = "exit 0"
+        basic.subcommands.other = "exit 1"
+        basic.order = """
+        test {
+            Signal(9) => killed,
+            1|Success => other
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains_ordered!(
+        output,
+        [
+            "sh, [\"-c\", \"echo start && sh -c 'kill -9 $$'\"]",
+            "sh, [\"-c\", \"exit 0\"]"
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_signaled_order_control() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.subcommands.test = "kill -15 $$"
+        basic.subcommands.killed = "exit 0"
+        basic.order = """
+        test {
+            Signaled => killed
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains_ordered!(
+        output,
+        ["sh, [\"-c\", \"kill -15 $$\"]", "sh, [\"-c\", \"exit 0\"]"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn returns_shell_exit_code_on_signal() {
+    expect_exit_code!(
+        137,
+        r#"
+        [scripts]
+        basic = "kill -9 $$"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_finally_after_ordered_subcommands() {
+    let output = expect_exit_code!(
+        3,
+        r#"
+        [scripts]
+        basic.subcommands.test = "exit 3"
+        basic.subcommands.other = "exit 0"
+        basic.subcommands.teardown = "echo teardown"
+        basic.order = """
+        test {
+            Success => other
+        }
+        """
+        basic.finally = ["teardown"]
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains_ordered!(
+        output,
+        [
+            "sh, [\"-c\", \"exit 3\"]",
+            "sh, [\"-c\", \"echo teardown\"]"
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn returns_cleanup_exit_code_on_finally_failure() {
+    expect_exit_code!(
+        4,
+        r#"
+        [scripts]
+        basic.subcommands.test = "exit 0"
+        basic.subcommands.teardown = "exit 4"
+        basic.order = "test"
+        basic.finally = ["teardown"]
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn runs_finally_after_error_in_ordered_subcommands() {
+    let output = expect_error!(
+        r#"
+        [scripts]
+        basic.subcommands.test.cmd.exec = "exit 0"
+        basic.subcommands.test.cmd.shell = ["bonnie-nonexistent-shell", "{COMMAND}"]
+        basic.subcommands.teardown = "echo teardown"
+        basic.order = "test"
+        basic.finally = ["teardown"]
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains!(output, ["sh, [\"-c\", \"echo teardown\"]"]);
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn returns_error_on_finally_without_order() {
+    expect_error!(
+        r#"
+        [scripts]
+        basic.cmd = "exit 0"
+        basic.finally = ["teardown"]
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_deps_run_once_in_order() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        setup = "echo setup"
+        lint.cmd = "echo lint"
+        lint.deps = ["setup"]
+        test.cmd = "echo test"
+        test.deps = ["setup", "lint"]
+        build.cmd = "echo build"
+        build.deps = ["lint", "test"]
+        "#,
+        BONNIE_VERSION,
+        ["build"]
+    );
+    assert_eq!(
+        output,
+        vec![
+            "sh, [\"-c\", \"echo setup\"]",
+            "sh, [\"-c\", \"echo lint\"]",
+            "sh, [\"-c\", \"echo test\"]",
+            "sh, [\"-c\", \"echo build\"]"
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn returns_dep_exit_code_and_stops_on_failure() {
+    let output = expect_exit_code!(
+        2,
+        r#"
+        [scripts]
+        setup = "exit 2"
+        build.cmd = "echo build"
+        build.deps = ["setup"]
+        "#,
+        BONNIE_VERSION,
+        ["build"]
+    );
+    assert!(!output.contains(&"sh, [\"-c\", \"echo build\"]".to_string()));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_subcommand_deps() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        backend.subcommands.setup = "echo setup"
+        build.cmd = "echo build"
+        build.deps = ["backend.setup"]
+        "#,
+        BONNIE_VERSION,
+        ["build"]
+    );
+    assert_contains_ordered!(
+        output,
+        [
+            "sh, [\"-c\", \"echo setup\"]",
+            "sh, [\"-c\", \"echo build\"]"
+        ]
+    );
+}
+#[test]
+fn returns_error_on_dep_cycle() {
+    // This should fail even though the script being run isn't part of the cycle
+    expect_error!(
+        r#"
+        [scripts]
+        basic = "exit 0"
+        lint.cmd = "exit 0"
+        lint.deps = ["test"]
+        test.cmd = "exit 0"
+        test.deps = ["lint"]
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+fn returns_error_on_unknown_dep() {
+    expect_error!(
+        r#"
+        [scripts]
+        basic.cmd = "exit 0"
+        basic.deps = ["nonexistent"]
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn prints_dep_order_on_dry_run() {
+    let cfg_str = "version = \"".to_string()
+        + BONNIE_VERSION
+        + "\"\n"
+        + r#"
+        [scripts]
+        setup = "echo setup"
+        build.cmd = "echo build"
+        build.deps = ["setup"]
+        "#;
+    let mut output = Vec::new();
+    let cfg = Config::new(&cfg_str)
+        .unwrap()
+        .to_final(BONNIE_VERSION, &mut output)
+        .unwrap();
+    let (command_to_run, command_name, relevant_args) =
+        cfg.get_command_for_args(&["build".to_string()]).unwrap();
+    let bone = command_to_run
+        .prepare(&command_name, &relevant_args, &cfg, &mut output)
+        .unwrap();
+    bone.dry_run(&command_name, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(
+        output.lines().collect::<Vec<&str>>(),
+        vec![
+            "Would run these scripts in order: setup -> build.",
+            "Would run 'setup' as command 'sh' with arguments '[\"-c\", \"echo setup\"]'.",
+            "Would run 'build' as command 'sh' with arguments '[\"-c\", \"echo build\"]'."
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_vars_interpolation() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [vars]
+        registry = "registry.example.com"
+        image = "%[vars.registry]/app:%[env.USER]"
+        [scripts]
+        basic.cmd = "echo %[vars.image]"
+        basic.desc = "pushes %[vars.image]"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    let user = std::env::var("USER").unwrap();
+    assert_contains!(
+        output,
+        [format!(
+            "sh, [\"-c\", \"echo registry.example.com/app:{}\"]",
+            user
+        )]
+    );
+}
+#[test]
+fn returns_error_on_vars_cycle() {
+    expect_error!(
+        r#"
+        [vars]
+        a = "%[vars.b]"
+        b = "%[vars.a]"
+        [scripts]
+        basic = "exit 0"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+fn returns_error_on_unknown_var() {
+    expect_error!(
+        r#"
+        [scripts]
+        basic = "echo %[vars.nonexistent]"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_aliases() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        build.cmd = "echo build"
+        build.aliases = ["b", "bld"]
+        backend.subcommands.deploy.cmd = "echo deploy"
+        backend.subcommands.deploy.aliases = ["d"]
+        backend.aliases = ["be"]
+        "#,
+        BONNIE_VERSION,
+        ["bld"]
+    );
+    assert_contains!(output, ["sh, [\"-c\", \"echo build\"]"]);
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        backend.subcommands.deploy.cmd = "echo deploy"
+        backend.subcommands.deploy.aliases = ["d"]
+        backend.aliases = ["be"]
+        "#,
+        BONNIE_VERSION,
+        ["be", "d"]
+    );
+    assert_contains!(output, ["sh, [\"-c\", \"echo deploy\"]"]);
+}
+#[test]
+fn returns_error_on_alias_clashing_with_name() {
+    expect_error!(
+        r#"
+        [scripts]
+        build.cmd = "exit 0"
+        build.aliases = ["test"]
+        test = "exit 0"
+        "#,
+        BONNIE_VERSION,
+        ["build"]
+    );
+}
+#[test]
+fn returns_error_on_duplicate_alias() {
+    expect_error!(
+        r#"
+        [scripts]
+        build.cmd = "exit 0"
+        build.aliases = ["b"]
+        bench.cmd = "exit 0"
+        bench.aliases = ["b"]
+        "#,
+        BONNIE_VERSION,
+        ["build"]
+    );
+}
+#[test]
+fn documents_aliases_next_to_name() {
+    let cfg_str = "version = \"".to_string()
+        + BONNIE_VERSION
+        + "\"\n"
+        + r#"
+        [scripts]
+        build.cmd = "exit 0"
+        build.desc = "builds everything"
+        build.aliases = ["b", "bld"]
+        "#;
+    let cfg = Config::new(&cfg_str)
+        .unwrap()
+        .to_final(BONNIE_VERSION, &mut Vec::new())
+        .unwrap();
+    let doc = cfg.document(Some("b".to_string())).unwrap();
+    assert!(doc.ends_with("build, b, bld    builds everything"));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_private_scripts_used_internally() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        _setup = "echo setup"
+        lint.cmd = "echo lint"
+        lint.private = true
+        build.cmd = "echo build"
+        build.deps = ["_setup", "lint"]
+        "#,
+        BONNIE_VERSION,
+        ["build"]
+    );
+    assert_contains_ordered!(
+        output,
+        [
+            "sh, [\"-c\", \"echo setup\"]",
+            "sh, [\"-c\", \"echo lint\"]",
+            "sh, [\"-c\", \"echo build\"]"
+        ]
+    );
+}
+#[test]
+fn returns_error_on_direct_private_invocation() {
+    expect_error!(
+        r#"
+        [scripts]
+        _setup = "exit 0"
+        "#,
+        BONNIE_VERSION,
+        ["_setup"]
+    );
+    expect_error!(
+        r#"
+        [scripts]
+        backend.subcommands.setup.cmd = "exit 0"
+        backend.subcommands.setup.private = true
+        "#,
+        BONNIE_VERSION,
+        ["backend", "setup"]
+    );
+}
+#[test]
+fn hides_private_scripts_from_documentation() {
+    let cfg_str = "version = \"".to_string()
+        + BONNIE_VERSION
+        + "\"\n"
+        + r#"
+        [scripts]
+        _setup = "exit 0"
+        build = "exit 0"
+        backend.subcommands.deploy = "exit 0"
+        backend.subcommands.migrate.cmd = "exit 0"
+        backend.subcommands.migrate.private = true
+        "#;
+    let cfg = Config::new(&cfg_str)
+        .unwrap()
+        .to_final(BONNIE_VERSION, &mut Vec::new())
+        .unwrap();
+    let doc = cfg.document(None).unwrap();
+    assert!(doc.contains("build"));
+    assert!(doc.contains("deploy"));
+    assert!(!doc.contains("_setup"));
+    assert!(!doc.contains("migrate"));
+    assert!(cfg.document(Some("_setup".to_string())).is_err());
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn warns_on_unknown_keys() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        deploy.subcommands.prod.cmd = "exit 0"
+        deploy.subcommands.prod.envvars = ["USER"]
+        deploy.subcommands.prod.descr = "deploys to production"
+        "#,
+        BONNIE_VERSION,
+        ["deploy", "prod"]
+    );
+    assert_contains!(output, ["Warning: unknown key 'scripts.deploy.subcommands.prod.envvars' (did you mean 'scripts.deploy.subcommands.prod.env_vars'?) in your Bonnie configuration file. It will be ignored (set `strict = true` to make this an error)."]);
+    assert_contains!(output, ["Warning: unknown key 'scripts.deploy.subcommands.prod.descr' (did you mean 'scripts.deploy.subcommands.prod.desc'?) in your Bonnie configuration file. It will be ignored (set `strict = true` to make this an error)."]);
+}
+#[test]
+fn returns_error_on_unknown_keys_when_strict() {
+    expect_error!(
+        r#"
+        strict = true
+        [scripts]
+        basic.cmd.exec = "exit 0"
+        basic.cmd.shel = ["sh", "-c", "{COMMAND}"]
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+fn reports_unknown_keys_when_deserialization_fails() {
+    // Without `exec`, this doesn't match any form of a command
+    let cfg_str = "version = \"".to_string()
+        + BONNIE_VERSION
+        + "\"\n"
+        + r#"
+        [scripts]
+        basic.cmd.exce = "exit 0"
+        "#;
+    let err = Config::new(&cfg_str).unwrap_err();
+    assert!(err
+        .contains("unknown key 'scripts.basic.cmd.exce' (did you mean 'scripts.basic.cmd.exec'?)"));
+}
+#[test]
+fn points_at_source_of_parsing_errors() {
+    let cfg_str = "version = \"".to_string()
+        + BONNIE_VERSION
+        + "\"\n"
+        + r#"[scripts]
+basic.subcommands.test = "exit 0"
+basic.cmd = "exit 0"
+basic.order = "test"
+"#;
+    let err = Config::new(&cfg_str)
+        .unwrap()
+        .to_final(BONNIE_VERSION, &mut Vec::new())
+        .unwrap_err();
+    assert!(err.ends_with(
+        " --> bonnie configuration:4:7
+  |
+4 | basic.cmd = \"exit 0\"
+  |       ^^^"
+    ));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_env_var_fallbacks() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic = "echo %{BONNIE_TEST_UNSET_PORT:-8080} %{USER:-nobody} [%{BONNIE_TEST_UNSET_OPTIONAL:-}] %{USER:?must be set}"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    let user = std::env::var("USER").unwrap();
+    assert_contains!(
+        output,
+        [format!("sh, [\"-c\", \"echo 8080 {} [] {}\"]", user, user)]
+    );
+}
+#[test]
+fn returns_custom_error_on_required_env_var() {
+    let res = run_e2e_test(
+        &("version = \"".to_string()
+            + BONNIE_VERSION
+            + "\"\n"
+            + r#"
+            [scripts]
+            basic = "echo %{BONNIE_TEST_UNSET_TOKEN:?a deploy token is needed}"
+            "#),
+        vec!["basic".to_string()],
+        BONNIE_VERSION,
+        &mut Vec::new(),
+    );
+    assert_eq!(res, Err("The environment variable 'BONNIE_TEST_UNSET_TOKEN' couldn't be loaded or was empty: a deploy token is needed".to_string()));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_prefixed_argument_names() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.cmd = "echo %a %ab %{a}b && exit 0"
+        basic.args = ["a", "ab"]
+        "#,
+        BONNIE_VERSION,
+        ["basic", "first", "second"]
+    );
+    assert_contains!(
+        output,
+        ["sh, [\"-c\", \"echo first second firstb && exit 0\"]"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn does_not_reinterpolate_argument_values() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.cmd = "echo %first %second %% && exit 0"
+        basic.args = ["first", "second"]
+        "#,
+        BONNIE_VERSION,
+        ["basic", "%second", "%%", "extra"]
+    );
+    assert_contains!(
+        output,
+        ["sh, [\"-c\", \"echo %second %% extra && exit 0\"]"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_escaped_interpolations() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.cmd = "echo \\%name %name \\%{name} 100%[prev.code] && exit 0"
+        basic.args = ["name"]
+        "#,
+        BONNIE_VERSION,
+        ["basic", "Name"]
+    );
+    assert_contains!(
+        output,
+        ["sh, [\"-c\", \"echo %name Name %{name} 100%[prev.code] && exit 0\"]"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn quotes_interpolated_arguments() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.cmd = "touch %file && echo %% && exit 0"
+        basic.args = ["file"]
+        "#,
+        BONNIE_VERSION,
+        [
+            "basic",
+            "/tmp/bonnie test's file.txt",
+            "; exit 1",
+            "$(exit 1)",
+            ""
+        ]
+    );
+    assert_contains!(
+        output,
+        ["sh, [\"-c\", \"touch '/tmp/bonnie test'\\\\''s file.txt' && echo '; exit 1' '$(exit 1)' '' && exit 0\"]"]
+    );
+    assert!(std::path::Path::new("/tmp/bonnie test's file.txt").exists());
+    let _ = std::fs::remove_file("/tmp/bonnie test's file.txt");
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn warns_about_arguments_for_unknown_shells() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.cmd.exec = "echo %name"
+        basic.cmd.shell = ["env", "sh", "-c", "{COMMAND}"]
+        basic.args = ["name"]
+        "#,
+        BONNIE_VERSION,
+        ["basic", "two words"]
+    );
+    assert_contains_ordered!(
+        output,
+        [
+            "Warning: the command 'basic' runs in a shell Bonnie doesn't know how to quote arguments for (only POSIX shells and PowerShell are supported), so arguments have been inserted as they are. Make sure they don't contain anything your shell will interpret, or use a supported shell.",
+            "env, [\"sh\", \"-c\", \"echo two words\"]"
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_raw_arguments() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.cmd = "echo %raw:flags %{raw:flags}x %flags && exit 0"
+        basic.args = ["flags"]
+        "#,
+        BONNIE_VERSION,
+        ["basic", "-a -b"]
+    );
+    assert_contains!(
+        output,
+        ["sh, [\"-c\", \"echo -a -b -a -bx '-a -b' && exit 0\"]"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_builtins() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.subcommands.inner = "echo %[script] %[os] %[arch] %[bonnie_version] %[cwd] %[config_dir] && exit 0"
+        "#,
+        BONNIE_VERSION,
+        ["basic", "inner"]
+    );
+    // Without a path to the config file, it's assumed to be in the current directory
+    let cwd = std::env::current_dir().unwrap();
+    let cwd = cwd.to_string_lossy();
+    assert_contains!(
+        output,
+        [format!(
+            "sh, [\"-c\", \"echo basic.inner linux {} {} {} {} && exit 0\"]",
+            std::env::consts::ARCH,
+            BONNIE_VERSION,
+            cwd,
+            cwd
+        )]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn succeeds_with_timestamp_builtin() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic = "test %[timestamp] -gt 1600000000"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert!(!output.iter().any(|line| line.contains("%[timestamp]")));
+}
+#[test]
+fn returns_error_on_surplus_args() {
+    let res = run_e2e_test(
+        &("version = \"".to_string()
+            + BONNIE_VERSION
+            + "\"\n"
+            + r#"
+            [scripts]
+            deploy.cmd = "echo %env"
+            deploy.args = ["env"]
+            "#),
+        vec![
+            "deploy".to_string(),
+            "prod".to_string(),
+            "--force".to_string(),
+        ],
+        BONNIE_VERSION,
+        &mut Vec::new(),
+    );
+    assert_eq!(res, Err("The command 'deploy' was given arguments it doesn't use: '--force'. Nothing in it inserts extra arguments with `%%`, so these would be ignored. Please remove them, or set `surplus_args = \"warn\"` in your Bonnie configuration to ignore them with a warning.".to_string()));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn warns_on_surplus_args_if_configured() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        surplus_args = "warn"
+        [scripts]
+        deploy.cmd = "echo %env"
+        deploy.args = ["env"]
+        "#,
+        BONNIE_VERSION,
+        ["deploy", "prod", "--force", "--yes"]
+    );
+    assert_contains_ordered!(
+        output,
+        [
+            "Warning: ignoring arguments the command 'deploy' doesn't use: '--force', '--yes'.",
+            "sh, [\"-c\", \"echo prod\"]"
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn allows_surplus_args_used_by_any_ordered_subcommand() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.subcommands.first = "echo first"
+        basic.subcommands.second = "echo %%"
+        basic.order = """
+        first {
+            Any => second
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["basic", "extra"]
+    );
+    assert_contains_ordered!(
+        output,
+        [
+            "sh, [\"-c\", \"echo first\"]",
+            "sh, [\"-c\", \"echo extra\"]"
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn masks_secret_env_vars_in_output() {
+    std::env::set_var("BONNIE_TEST_SECRET_TOKEN", "hunter2");
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        deploy.cmd = "test %BONNIE_TEST_SECRET_TOKEN = hunter2 && echo %BONNIE_TEST_SECRET_TOKEN%[os]"
+        deploy.secret_env_vars = ["BONNIE_TEST_SECRET_TOKEN"]
+        "#,
+        BONNIE_VERSION,
+        ["deploy"]
+    );
+    // The command still has to get the real value (or `test` would fail)
+    assert_contains!(
+        output,
+        ["sh, [\"-c\", \"test *** = *** && echo ***linux\"]"]
+    );
+    assert!(!output.iter().any(|line| line.contains("hunter2")));
+}
+#[test]
+fn masks_secret_env_vars_in_dry_run() {
+    std::env::set_var("BONNIE_TEST_SECRET_KEY", "s3cr3t");
+    let mut output = Vec::new();
+    let cfg = Config::new(
+        &("version = \"".to_string()
+            + BONNIE_VERSION
+            + "\"\n"
+            + r#"
+            [scripts]
+            deploy.cmd = "curl -H 'Authorization: %BONNIE_TEST_SECRET_KEY' example.com"
+            deploy.secret_env_vars = ["BONNIE_TEST_SECRET_KEY"]
+            "#),
+    )
+    .unwrap()
+    .to_final(BONNIE_VERSION, &mut output)
+    .unwrap();
+    let (command_to_run, command_name, relevant_args) =
+        cfg.get_command_for_args(&["deploy".to_string()]).unwrap();
+    let bone = command_to_run
+        .prepare(&command_name, &relevant_args, &cfg, &mut output)
+        .unwrap();
+    bone.dry_run(&command_name, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("Authorization: ***"));
+    assert!(!output.contains("s3cr3t"));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn skips_commands_whose_conditions_arent_met() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.cmd = "exit 1"
+        basic.when = { env = "BONNIE_TEST_UNSET_CI" }
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert!(!output.iter().any(|line| line.starts_with("sh")));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn runs_commands_whose_conditions_are_met() {
+    let output = expect_exit_code!(
+        1,
+        r#"
+        [scripts]
+        basic.cmd = "exit 1"
+        basic.when = { file_exists = "Cargo.toml", command_exists = "sh", env = "USER" }
+        basic.else = "exit 2"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains!(output, ["sh, [\"-c\", \"exit 1\"]"]);
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn runs_else_when_conditions_arent_met() {
+    let output = expect_exit_code!(
+        2,
+        r#"
+        [scripts]
+        basic.cmd = "exit 1"
+        basic.when = { command_exists = "bonnie-test-nonexistent-command" }
+        basic.else = "exit 2"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains!(output, ["sh, [\"-c\", \"exit 2\"]"]);
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn checks_conditions_against_script_env_files() {
+    std::fs::write(
+        "/tmp/bonnie_test_script_env_2.env",
+        "BONNIE_TEST_CONDITION_ENV=file\n",
+    )
+    .unwrap();
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.cmd = "exit 0"
+        basic.when = { env = "BONNIE_TEST_CONDITION_ENV=file" }
+        basic.else = "exit 1"
+        basic.env_files = ["/tmp/bonnie_test_script_env_2.env"]
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_contains!(output, ["sh, [\"-c\", \"exit 0\"]"]);
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn treats_skipped_subcommands_as_successes() {
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.subcommands.docker.cmd = "exit 1"
+        basic.subcommands.docker.when = { file_exists = "bonnie-test-nonexistent-file" }
+        basic.subcommands.other = "exit 0"
+        basic.order = """
+        docker {
+            Success => other
+        }
+        """
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+    assert_eq!(
+        output
+            .iter()
+            .filter(|line| line.starts_with("sh"))
+            .collect::<Vec<&String>>(),
+        vec!["sh, [\"-c\", \"exit 0\"]"]
+    );
+}
+#[test]
+fn returns_error_on_else_without_when() {
+    expect_error!(
+        r#"
+        [scripts]
+        basic.cmd = "exit 0"
+        basic.else = "exit 1"
+        "#,
+        BONNIE_VERSION,
+        ["basic"]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn loads_script_env_files_only_for_that_script() {
+    std::fs::write(
+        "/tmp/bonnie_test_script_env_0.env",
+        "SHORTGREETING=Howdy\nBONNIE_TEST_SCRIPT_ONLY=yes\n",
+    )
+    .unwrap();
+    let output = expect_exit_code!(
+        0,
+        r#"
+        env_files = ["src/.env"]
+        [scripts]
+        check = "test -z \"$BONNIE_TEST_SCRIPT_ONLY\""
+        deploy.cmd = "echo %SHORTGREETING && test \"$BONNIE_TEST_SCRIPT_ONLY\" = yes"
+        deploy.env_vars = ["SHORTGREETING"]
+        deploy.env_files = ["/tmp/bonnie_test_script_env_0.env", "?/tmp/bonnie_test_script_env_nonexistent.env"]
+        deploy.deps = ["check"]
+        "#,
+        BONNIE_VERSION,
+        ["deploy"]
+    );
+    // Script files take precedence over global ones, but dependencies don't see them at all
+    assert_contains_ordered!(
+        output,
+        [
+            "sh, [\"-c\", \"test -z \\\"$BONNIE_TEST_SCRIPT_ONLY\\\"\"]",
+            "sh, [\"-c\", \"echo Howdy && test \\\"$BONNIE_TEST_SCRIPT_ONLY\\\" = yes\"]"
+        ]
+    );
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn prefers_real_env_to_script_env_files() {
+    std::env::set_var("BONNIE_TEST_REAL_ENV", "real");
+    std::fs::write(
+        "/tmp/bonnie_test_script_env_1.env",
+        "BONNIE_TEST_REAL_ENV=file\n",
+    )
+    .unwrap();
+    let output = expect_exit_code!(
+        0,
+        r#"
+        [scripts]
+        basic.subcommands.inner.cmd = "echo %BONNIE_TEST_REAL_ENV && test \"$BONNIE_TEST_REAL_ENV\" = real"
+        basic.subcommands.inner.env_vars = ["BONNIE_TEST_REAL_ENV"]
+        basic.env_files = ["/tmp/bonnie_test_script_env_1.env"]
+        "#,
+        BONNIE_VERSION,
+        ["basic", "inner"]
+    );
+    assert_contains!(
+        output,
+        ["sh, [\"-c\", \"echo real && test \\\"$BONNIE_TEST_REAL_ENV\\\" = real\"]"]
+    );
+}
+#[test]
+fn returns_error_on_missing_required_script_env_file() {
+    let res = run_e2e_test(
+        &("version = \"".to_string()
+            + BONNIE_VERSION
+            + "\"\n"
+            + r#"
+            [scripts]
+            basic.cmd = "exit 0"
+            basic.env_files = ["/tmp/bonnie_test_script_env_nonexistent.env"]
+            "#),
+        vec!["basic".to_string()],
+        BONNIE_VERSION,
+        &mut Vec::new(),
+    );
+    assert_eq!(res, Err("Requested environment variable file '/tmp/bonnie_test_script_env_nonexistent.env' could not be loaded. Either the file doesn't exist, Bonnie doesn't have the permissions necessary to access it, or something inside it can't be processed.".to_string()));
+}
@@ -0,0 +1,64 @@
+// These tests check that scripts are selected for the right target, including when it's overridden
+// `BONNIE_TARGET` is process-wide, so everything that sets it is kept in this one test (otherwise tests running in parallel would see it)
+
+use lib::{Config, BONNIE_VERSION};
+
+#[test]
+fn selects_overridden_target() {
+    let cfg = Config::new(
+        &("version = \"".to_string()
+            + BONNIE_VERSION
+            + "\"\n"
+            + r#"
+            [scripts]
+            build.cmd.generic = "echo generic"
+            build.cmd.targets.windows = "echo windows"
+            build.cmd.targets.unix = "echo unix"
+            build.cmd.targets.aarch64-linux = "echo aarch64-linux"
+            build.cmd.targets.linux-musl = "echo linux-musl"
+            build.cmd.targets.gnu = "echo gnu"
+            "#),
+    )
+    .unwrap()
+    .to_final(BONNIE_VERSION, &mut Vec::new())
+    .unwrap();
+    let dry_run = |target: &str| {
+        std::env::set_var("BONNIE_TARGET", target);
+        let mut output = Vec::new();
+        let (command_to_run, command_name, relevant_args) =
+            cfg.get_command_for_args(&["build".to_string()]).unwrap();
+        let bone = command_to_run
+            .prepare(&command_name, &relevant_args, &cfg, &mut output)
+            .unwrap();
+        bone.dry_run(&command_name, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    // The default shell is selected by the same logic
+    assert_eq!(
+        dry_run("x86_64-pc-windows-msvc"),
+        "Would run 'build' as command 'powershell' with arguments '[\"-command\", \"echo windows\"]'.\n"
+    );
+    assert_eq!(
+        dry_run("aarch64-unknown-linux-gnu"),
+        "Would run 'build' as command 'sh' with arguments '[\"-c\", \"echo aarch64-linux\"]'.\n"
+    );
+    assert_eq!(
+        dry_run("x86_64-unknown-linux-musl"),
+        "Would run 'build' as command 'sh' with arguments '[\"-c\", \"echo linux-musl\"]'.\n"
+    );
+    assert_eq!(
+        dry_run("x86_64-unknown-freebsd"),
+        "Would run 'build' as command 'sh' with arguments '[\"-c\", \"echo unix\"]'.\n"
+    );
+    // A pair starting with an OS is an OS and an environment, not an architecture and an OS
+    assert_eq!(
+        dry_run("linux-gnu"),
+        "Would run 'build' as command 'sh' with arguments '[\"-c\", \"echo gnu\"]'.\n"
+    );
+    assert_eq!(
+        dry_run("wasm32-unknown-unknown"),
+        "Would run 'build' as command 'sh' with arguments '[\"-c\", \"echo generic\"]'.\n"
+    );
+    std::env::remove_var("BONNIE_TARGET");
+}
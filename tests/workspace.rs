@@ -0,0 +1,155 @@
+// These tests run the actual Bonnie executable, because workspace members are run by separate Bonnie processes
+// Each test in this file MUST have a separate temporary directory to write to, otherwise undefined conflicts occur!
+
+use lib::BONNIE_VERSION;
+use std::fs;
+use std::process::Command;
+
+// A testing utility that writes the given configs (paths relative to the given directory) and runs Bonnie in that directory with the given arguments
+// Every config given here will have a version tag added (in YAML syntax for YAML files)
+// This returns the exit code of Bonnie and everything it (and its members) printed
+#[cfg(test)]
+fn run_in_workspace(dir: &str, cfgs: Vec<(&str, &str)>, args: Vec<&str>) -> (i32, String) {
+    let _ = fs::remove_dir_all(dir);
+    for (path, cfg) in cfgs {
+        let path = format!("{}/{}", dir, path);
+        fs::create_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+        let version_tag = if path.ends_with(".yaml") || path.ends_with(".yml") {
+            format!("version: \"{}\"\n", BONNIE_VERSION)
+        } else {
+            format!("version = \"{}\"\n", BONNIE_VERSION)
+        };
+        fs::write(path, version_tag + cfg).unwrap();
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_bonnie"))
+        .args(args)
+        .current_dir(dir)
+        .env_remove("BONNIE_CONF")
+        .env_remove("BONNIE_CACHE")
+        .output()
+        .unwrap();
+
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn runs_script_in_every_member() {
+    let (exit_code, output) = run_in_workspace(
+        "/tmp/bonnie_test_workspace_0",
+        vec![
+            (
+                "bonnie.toml",
+                "workspace.members = [\"services/*\"]\n[scripts]\nbasic = \"exit 0\"",
+            ),
+            ("services/a/bonnie.toml", "[scripts]\ntest = \"pwd\""),
+            ("services/b/bonnie.toml", "[scripts]\ntest = \"exit 3\""),
+            ("services/c/bonnie.toml", "[scripts]\nother = \"exit 0\""),
+        ],
+        vec!["--all", "test"],
+    );
+    assert_eq!(exit_code, 3);
+    // Members should be run in their own directories
+    assert!(output.contains("/tmp/bonnie_test_workspace_0/services/a\n"));
+    assert!(output.contains(
+        "Skipping workspace member 'services/c', which doesn't define the 'test' script."
+    ));
+    assert!(output.contains(
+        "Workspace summary for 'test':
+    services/a: succeeded
+    services/b: failed with exit code 3
+    services/c: skipped"
+    ));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn runs_members_in_parallel() {
+    let (exit_code, output) = run_in_workspace(
+        "/tmp/bonnie_test_workspace_1",
+        vec![
+            (
+                "bonnie.toml",
+                "workspace.members = [\"a\", \"b\"]\nworkspace.parallel = true\n[scripts]\nbasic = \"exit 0\"",
+            ),
+            ("a/bonnie.toml", "[scripts]\ntest = \"exit 0\""),
+            ("b/bonnie.toml", "[scripts]\ntest = \"exit 0\""),
+        ],
+        vec!["--all", "test"],
+    );
+    assert_eq!(exit_code, 0);
+    assert!(output.contains("a: succeeded\n    b: succeeded"));
+}
+#[test]
+fn returns_error_without_workspace() {
+    let (exit_code, _) = run_in_workspace(
+        "/tmp/bonnie_test_workspace_2",
+        vec![("bonnie.toml", "[scripts]\ntest = \"exit 0\"")],
+        vec!["--all", "test"],
+    );
+    assert_eq!(exit_code, 1);
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn runs_script_by_alias_in_members() {
+    let (exit_code, output) = run_in_workspace(
+        "/tmp/bonnie_test_workspace_3",
+        vec![
+            (
+                "bonnie.toml",
+                "workspace.members = [\"a\"]\n[scripts]\nbasic = \"exit 0\"",
+            ),
+            (
+                "a/bonnie.toml",
+                "[scripts]\ntest.cmd = \"echo tested\"\ntest.aliases = [\"t\"]",
+            ),
+        ],
+        vec!["--all", "t"],
+    );
+    assert_eq!(exit_code, 0);
+    assert!(output.contains("tested\n"));
+    assert!(output.contains("    a: succeeded"));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn skips_members_where_script_is_private() {
+    let (exit_code, output) = run_in_workspace(
+        "/tmp/bonnie_test_workspace_4",
+        vec![
+            (
+                "bonnie.toml",
+                "workspace.members = [\"a\"]\n[scripts]\nbasic = \"exit 0\"",
+            ),
+            (
+                "a/bonnie.toml",
+                "[scripts]\ntest.cmd = \"exit 0\"\ntest.private = true",
+            ),
+        ],
+        vec!["--all", "test"],
+    );
+    assert_eq!(exit_code, 0);
+    assert!(
+        output.contains("Skipping workspace member 'a', which doesn't define the 'test' script.")
+    );
+    assert!(output.contains("    a: skipped"));
+}
+#[test]
+#[cfg(target_os = "linux")] // This test will only work on Linux
+fn finds_member_configs_in_other_formats() {
+    let (exit_code, output) = run_in_workspace(
+        "/tmp/bonnie_test_workspace_5",
+        vec![
+            (
+                "bonnie.toml",
+                "workspace.members = [\"a\"]\n[scripts]\nbasic = \"exit 0\"",
+            ),
+            ("a/bonnie.yaml", "scripts:\n  test: echo tested\n"),
+        ],
+        vec!["--all", "test"],
+    );
+    assert_eq!(exit_code, 0);
+    assert!(output.contains("tested\n"));
+    assert!(output.contains("    a: succeeded"));
+}